@@ -0,0 +1,40 @@
+//! Terrain lookups of GeoFile: cargo bench --bench geo_file
+//! The fixture is a 256x256 raster of synthetic hills with a no-data lake (EPSG:4326, Int16, 64x64 deflated tiles)
+//! spanning 4 x 6 deg around the OGN_APRS_FILTER position.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/worker/geo_file.rs"]
+mod geo_file;
+
+use geo_file::GeoFile;
+
+const FIXTURE_FILEPATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/data/dem-fixture.tif");
+const CENTER_LAT: f64 = 49.1234;
+const CENTER_LON: f64 = 16.4567;
+
+/// Pseudo-random positions all over the fixture.
+fn coords(n: usize) -> Vec<(f64, f64)> {
+    let mut seed = 0x2545F4914F6CDD1D_u64;
+    let mut rnd = || { seed ^= seed << 13; seed ^= seed >> 7; seed ^= seed << 17; (seed % 10_000) as f64 / 10_000.0 };
+
+    (0..n).map(|_| (CENTER_LAT + rnd() * 4.0 - 2.0, CENTER_LON + rnd() * 6.0 - 3.0)).collect()
+}
+
+fn get_value(c: &mut Criterion) {
+    let coords = coords(10_000);
+
+    // all 16 blocks cached vs. the minimum of four:
+    let mut group = c.benchmark_group("get_value");
+    for cache_size in [256 * 1024, 0] {
+        let mut geo_file = GeoFile::open(FIXTURE_FILEPATH, cache_size).unwrap();
+        group.bench_with_input(BenchmarkId::new("cache_size", cache_size), &coords, |b, coords| {
+            b.iter(|| coords.iter().filter(|(lat, lon)| geo_file.get_value(black_box(*lat), black_box(*lon)).is_some()).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_value);
+criterion_main!(benches);
@@ -1,73 +1,71 @@
 use std::collections::HashMap;
-use std::fmt;
-use std::fs;
 
-use log::{info};
+use log::info;
 
+mod airfield_record;
+pub use airfield_record::{AirfieldRecord, AirfieldType, Runway};
 
-// #[derive(Deserialize, Debug)]
-#[derive(Clone, Debug)]
-pub struct AirfieldRecord {
-    code: String,
-    lat: f64,
-    lon: f64,
-}
-
-impl AirfieldRecord {
+pub mod airfield_service;
+pub mod importers;
+mod kd_tree;
+use kd_tree::KdTree;
 
-    ///lat + lon in degrees
-    pub fn new(code: &str, lat: f64, lon:f64) -> AirfieldRecord {
-        AirfieldRecord { 
-            code: code.to_string(),
-            lat: lat.to_radians(),
-            lon: lon.to_radians(),
-        }
-    }
-}
 
-impl fmt::Display for AirfieldRecord {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "#AirfieldRecord: {0}; lat:{1:.4}; lon:{2:.4}", self.code, self.lat.to_degrees(), self.lon.to_degrees())
-    }
-}
+const RW_MAX_HEADING_DIFF: f64 = 30.0;  // [deg] max difference of the aircraft track and runway heading
 
 #[derive(Debug, Clone)]
 pub struct AirfieldManager {
-    airfields_in_quadrants: HashMap<i32, HashMap<i32, Vec<AirfieldRecord>>>,
+    airfields: Vec<AirfieldRecord>,
+    index: KdTree,
+    airfields_by_code: HashMap<String, usize>,   // code -> index into airfields
+    max_reach: f64,                             // [km] the farthest any airfield captures events
 }
 
 impl AirfieldManager {
     pub fn new(filepath: &str) -> AirfieldManager {
+        AirfieldManager::load(filepath).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Reads our airfields json, a SeeYou .cup or an OpenAIP airport export.
+    pub fn load(filepath: &str) -> Result<AirfieldManager, String> {
         info!("Reading airfields from '{filepath}'");
 
-        let mut airfields: Vec<AirfieldRecord> = AirfieldManager::load_airfields_from_file(filepath);
-        airfields.sort_by(|a, b| a.lat.total_cmp(&b.lat) ); // ordering by lon gives better & faster results
+        let airfields: Vec<AirfieldRecord> = importers::load_file(filepath)?;
 
-        let airfields_in_quadrants = AirfieldManager::split_airfields_into_quadrants(airfields);
+        Ok(AirfieldManager::from_records(airfields))
+    }
+
+    fn from_records(airfields: Vec<AirfieldRecord>) -> AirfieldManager {
+        let coords = airfields.iter().map(|af| (af.lat.to_degrees(), af.lon.to_degrees())).collect();
+        let index = KdTree::new(&coords);
+        let airfields_by_code = airfields.iter().enumerate().map(|(i, af)| (af.code.clone(), i)).collect();
+        let max_reach = airfields.iter().map(|af| af.reach()).fold(0_f64, f64::max);
 
         AirfieldManager {
-            airfields_in_quadrants,
+            airfields,
+            index,
+            airfields_by_code,
+            max_reach,
         }
     }
 
-    fn load_airfields_from_file(filepath: &str) -> Vec<AirfieldRecord> {
-        let data = fs::read_to_string(filepath).expect(&format!("Could not read '{filepath}'!"));
-        let json: serde_json::Value = serde_json::from_str(&data).expect(&format!("Could not parse json from '{filepath}'!"));
-        // let json: &Vec<AirfieldRecord> = serde_json::from_str(&data).unwrap();
-
-        let mut airfields: Vec<AirfieldRecord> = Vec::new();
-
-        for item in json.as_array().unwrap().into_iter() {
-            let ar = AirfieldRecord::new(
-                &item["code"].to_string().replace("\"", ""), 
-                item["lat"].to_string().parse().unwrap(), 
-                item["lon"].to_string().parse().unwrap()
-            );
-
-            airfields.push(ar);
-        }
+    pub fn len(&self) -> usize {
+        self.airfields.len()
+    }
 
-        airfields
+    /// Codes of airfields (added, removed, changed) in the other list compared to this one.
+    pub fn diff(&self, other: &AirfieldManager) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let added = other.airfields.iter().filter(|af| self.get(&af.code).is_none()).map(|af| af.code.clone()).collect();
+        let removed = self.airfields.iter().filter(|af| other.get(&af.code).is_none()).map(|af| af.code.clone()).collect();
+        let changed = other.airfields.iter()
+            .filter(|af| match self.get(&af.code) {
+                Some(old) => old.as_json() != af.as_json(),
+                None => false,
+            })
+            .map(|af| af.code.clone())
+            .collect();
+
+        (added, removed, changed)
     }
 
     /// all arguments in radians!
@@ -83,79 +81,64 @@ impl AirfieldManager {
         return dist
     }
 
-    fn split_airfields_into_quadrants(airfields: Vec<AirfieldRecord>) -> HashMap<i32, HashMap<i32, Vec<AirfieldRecord>>> {
-        let mut airfields_quads = HashMap::from([
-            (1, HashMap::from([
-                (1, Vec::<AirfieldRecord>::new()),
-                (-1, Vec::<AirfieldRecord>::new()),
-            ])),
-            (-1, HashMap::from([
-                (1, Vec::<AirfieldRecord>::new()),
-                (-1, Vec::<AirfieldRecord>::new()),
-            ])),
-        ]);
-
-        for af in airfields.into_iter() {
-            let lat_sign = if af.lat >= 0.0 {1}  else {-1};
-            let lon_sign = if af.lon >= 0.0 {1}  else {-1};
-            airfields_quads.get_mut(&lat_sign).unwrap().get_mut(&lon_sign).unwrap().push(af);
-        }
-    
-        airfields_quads
+    /// Initial bearing from point 1 to point 2; all arguments in radians!
+    /// @return [deg] 0..360
+    pub fn get_bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+        let y = (lon2 - lon1).sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * (lon2 - lon1).cos();
+
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
+    }
+
+    /// Picks the runway of an airfield the aircraft used.
+    /// @param bearing: [deg] track of the aircraft during the take-off or landing roll
+    /// @param lat, lon: [deg] a position on the runway (to tell parallel runways apart)
+    pub fn get_runway(&self, code: &str, bearing: f64, lat: f64, lon: f64) -> Option<String> {
+        let airfield = &self.airfields[*self.airfields_by_code.get(code)?];
+
+        let heading_diff = |rw: &Runway| {
+            let diff = (rw.heading - bearing).abs() % 360.0;
+            if diff > 180.0 { 360.0 - diff } else { diff }
+        };
+
+        airfield.runways.iter()
+            .filter(|rw| heading_diff(rw) <= RW_MAX_HEADING_DIFF)
+            .min_by(|a, b| a.cross_track_distance(lat, lon).total_cmp(&b.cross_track_distance(lat, lon)))
+            .map(|rw| rw.name.clone())
     }
 
     /// arguments in degrees
-    pub fn get_nearest(&self, lat: f64, lon:f64) -> Option<String> {
-        // pick the appropriate airfields list (NE / NW / SE / SW):
-        let lat_sign = if lat >= 0_f64 {1} else {-1};
-        let lon_sign = if lon >= 0_f64 {1} else {-1};
-        let airfields = self.airfields_in_quadrants.get(&lat_sign).unwrap().get(&lon_sign).unwrap();
-
-        let lat_rad = lat.to_radians();
-        let lon_rad = lon.to_radians();
-
-        let mut start_i = 0;
-        let mut end_i = airfields.len();
-        let mut n = 0;
-        loop {
-            let i = start_i + ((end_i - start_i) / 2) as usize;
-            if lat_rad < airfields.get(i).unwrap().lat {
-                end_i = i;
-            } else {
-                start_i = i;
-            }
-
-            if end_i - start_i <= 200 {
-                break;
-            }
-
-            n += 1;
-            if n > 100 {
-                break;
-            }
-        }
+    /// @param radius: [km]
+    /// @return (code, distance in km) of the nearest airfield within the radius
+    pub fn get_nearest_within(&self, lat: f64, lon: f64, radius: f64) -> Option<(String, f64)> {
+        self.index.nearest_within(lat, lon, radius).map(|(i, dist)| (self.airfields[i].code.clone(), dist))
+    }
 
-        let d: f64 = 1_f64.to_radians();
-        let end_range = if airfields.len() > end_i+1 { end_i + 1 } else { end_i };
-        let airfields_slice: Vec<&AirfieldRecord> = airfields[start_i..end_range].into_iter().filter(
-            |a| a.lon >= lon_rad - d && a.lon <= lon_rad + d
-            ).collect();
-
-        let mut min_dist = 99999999999999_f64;
-        let mut code: Option<String> = None;
-        for rec in airfields_slice.into_iter() {
-            let dist = AirfieldManager::get_distance_in_km(lat_rad, lon_rad, rec.lat, rec.lon);
-            if dist < min_dist {
-                min_dist = dist;
-                code = Some(rec.code.clone());
-            }
-        }
+    /// arguments in degrees
+    /// @param radius: [km]
+    /// @return (code, distance in km) of all airfields within the radius, the nearest first
+    pub fn get_all_within(&self, lat: f64, lon: f64, radius: f64) -> Vec<(String, f64)> {
+        self.index.all_within(lat, lon, radius).into_iter().map(|(i, dist)| (self.airfields[i].code.clone(), dist)).collect()
+    }
 
-        if min_dist < 8.0 {  // [km]
-            code
-        } else {
-            None
-        }
+    pub fn get(&self, code: &str) -> Option<&AirfieldRecord> {
+        self.airfields_by_code.get(code).map(|i| &self.airfields[*i])
+    }
+
+    /// The airfield an event at the position belongs to - an airfield whose boundary contains
+    /// the position or the nearest one within its own capture radius.
+    /// arguments in degrees
+    pub fn get_nearest(&self, lat: f64, lon:f64) -> Option<String> {
+        let candidates: Vec<(&AirfieldRecord, f64)> = self.index.all_within(lat, lon, self.max_reach).into_iter()
+            .map(|(i, dist)| (&self.airfields[i], dist))
+            .filter(|(af, dist)| af.captures(lat, lon, *dist))
+            .collect();
+
+        // candidates are ordered by distance:
+        candidates.iter()
+            .find(|(af, _)| af.boundary.len() > 0)
+            .or(candidates.first())
+            .map(|(af, _)| af.code.clone())
     }
 
     pub fn get_nearest_fn(filepath: &str) -> impl Fn(f64, f64) -> Option<String> {
@@ -166,4 +149,49 @@ impl AirfieldManager {
         };
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AirfieldManager, AirfieldRecord, Runway};
+
+    fn runway(name: &str, lat: f64, lon: f64, heading: f64) -> Runway {
+        Runway { name: name.into(), lat, lon, heading }
+    }
+
+    /// Parallel runways 36L/18R and 36R/18L ~290 m apart; the thresholds of 36x at the south end.
+    fn airfield_manager() -> AirfieldManager {
+        let mut airfield = AirfieldRecord::new("LKTB", 49.15, 16.69);
+        airfield.runways = vec![
+            runway("36L", 49.14, 16.690, 0.0),
+            runway("18R", 49.16, 16.690, 180.0),
+            runway("36R", 49.14, 16.694, 0.0),
+            runway("18L", 49.16, 16.694, 180.0),
+        ];
+
+        AirfieldManager::from_records(vec![airfield])
+    }
+
+    #[test]
+    fn runway_heading_wraps_around() {
+        let afm = airfield_manager();
+
+        assert_eq!(afm.get_runway("LKTB", 10.0, 49.15, 16.690), Some("36L".into()));
+        assert_eq!(afm.get_runway("LKTB", 350.0, 49.15, 16.690), Some("36L".into()));
+        assert_eq!(afm.get_runway("LKTB", 185.0, 49.15, 16.690), Some("18R".into()));
+
+        // across the runways:
+        assert_eq!(afm.get_runway("LKTB", 90.0, 49.15, 16.690), None);
+        assert_eq!(afm.get_runway("LKXX", 10.0, 49.15, 16.690), None);
+    }
+
+    #[test]
+    fn parallel_runways() {
+        let afm = airfield_manager();
+
+        assert_eq!(afm.get_runway("LKTB", 2.0, 49.145, 16.6905), Some("36L".into()));
+        assert_eq!(afm.get_runway("LKTB", 2.0, 49.145, 16.6935), Some("36R".into()));
+        assert_eq!(afm.get_runway("LKTB", 178.0, 49.155, 16.6905), Some("18R".into()));
+        assert_eq!(afm.get_runway("LKTB", 178.0, 49.155, 16.6935), Some("18L".into()));
+    }
+}
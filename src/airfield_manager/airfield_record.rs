@@ -0,0 +1,234 @@
+use std::fmt;
+
+use log::warn;
+use serde_json::{json, Value};
+
+use crate::airfield_manager::AirfieldManager;
+
+pub const AF_CAPTURE_RADIUS: f64 = 8.0; // [km] default distance within which events belong to the airfield
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AirfieldType {
+    Unknown,
+    GliderSite,
+    UltralightStrip,
+    Airport,
+    Heliport,
+}
+
+impl AirfieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AirfieldType::Unknown => "unknown",
+            AirfieldType::GliderSite => "glider",
+            AirfieldType::UltralightStrip => "ultralight",
+            AirfieldType::Airport => "airport",
+            AirfieldType::Heliport => "heliport",
+        }
+    }
+
+    pub fn from_str(s: &str) -> AirfieldType {
+        match s {
+            "glider" => AirfieldType::GliderSite,
+            "ultralight" => AirfieldType::UltralightStrip,
+            "airport" => AirfieldType::Airport,
+            "heliport" => AirfieldType::Heliport,
+            _ => AirfieldType::Unknown,
+        }
+    }
+}
+
+/// One direction of a runway.
+#[derive(Clone, Debug)]
+pub struct Runway {
+    pub name: String,   // e.g. "06", "24L"
+    pub lat: f64,       // [deg] threshold
+    pub lon: f64,       // [deg] threshold
+    pub heading: f64,   // [deg] true
+}
+
+impl Runway {
+    /// Distance of a point from the runway centerline (extended beyond the thresholds).
+    /// @return [km]
+    pub fn cross_track_distance(&self, lat: f64, lon: f64) -> f64 {
+        // flat earth is fine within the airfield:
+        let dx = (lon - self.lon) * 111.32 * self.lat.to_radians().cos();
+        let dy = (lat - self.lat) * 110.57;
+        let h = self.heading.to_radians();
+
+        (dx * h.cos() - dy * h.sin()).abs()
+    }
+}
+
+// #[derive(Deserialize, Debug)]
+#[derive(Clone, Debug)]
+pub struct AirfieldRecord {
+    pub code: String,
+    pub name: String,
+    pub lat: f64,   // [rad]
+    pub lon: f64,   // [rad]
+    pub elevation: Option<i32>,     // [m] AMSL
+    pub airfield_type: AirfieldType,
+    pub country: String,            // ISO 3166 alpha-2
+    pub capture_radius: f64,        // [km]
+    pub boundary: Vec<(f64, f64)>,  // [deg] (lat, lon) polygon; when present it takes precedence over the radius
+    pub runways: Vec<Runway>,
+}
+
+impl AirfieldRecord {
+
+    ///lat + lon in degrees
+    pub fn new(code: &str, lat: f64, lon:f64) -> AirfieldRecord {
+        AirfieldRecord {
+            code: code.to_string(),
+            name: "".into(),
+            lat: lat.to_radians(),
+            lon: lon.to_radians(),
+            elevation: None,
+            airfield_type: AirfieldType::Unknown,
+            country: "".into(),
+            capture_radius: AF_CAPTURE_RADIUS,
+            boundary: Vec::new(),
+            runways: Vec::new(),
+        }
+    }
+
+    /// {"code": "LKKA", "lat": 49.1, "lon": 16.1, "name": "Kunovice", "elevation": 176, "type": "airport", "country": "CZ",
+    ///  "radius": 3.5, "boundary": [[lat, lon], ..], "runways": [{"name": "06", "lat": 49.1, "lon": 16.1, "heading": 62}, ..]}
+    /// Only code, lat & lon are mandatory.
+    pub fn from_json(item: &Value) -> Option<AirfieldRecord> {
+        let mut ar = AirfieldRecord::new(
+            &item["code"].to_string().replace("\"", ""),
+            item["lat"].to_string().parse().ok()?,
+            item["lon"].to_string().parse().ok()?
+        );
+
+        if let Some(name) = item["name"].as_str() { ar.name = name.into(); }
+        ar.elevation = item["elevation"].as_i64().map(|e| e as i32);
+        if let Some(airfield_type) = item["type"].as_str() { ar.airfield_type = AirfieldType::from_str(airfield_type); }
+        if let Some(country) = item["country"].as_str() { ar.country = country.into(); }
+        if let Some(radius) = item["radius"].as_f64() { ar.capture_radius = radius; }
+
+        if let Some(boundary) = item["boundary"].as_array() {
+            ar.boundary = boundary.iter().filter_map(|p| Some((p[0].as_f64()?, p[1].as_f64()?))).collect();
+            if ar.boundary.len() < 3 {
+                warn!("Ignoring boundary of {} with less than 3 points", ar.code);
+                ar.boundary.clear();
+            }
+        }
+
+        if let Some(runways) = item["runways"].as_array() {
+            for rw in runways {
+                match (rw["name"].as_str(), rw["lat"].as_f64(), rw["lon"].as_f64(), rw["heading"].as_f64()) {
+                    (Some(name), Some(lat), Some(lon), Some(heading)) => ar.runways.push(Runway { name: name.into(), lat, lon, heading }),
+                    _ => warn!("Incomplete runway record at {}: {rw}", ar.code),
+                }
+            }
+        }
+
+        Some(ar)
+    }
+
+    pub fn as_json(&self) -> Value {
+        let mut item = json!({
+            "code": self.code,
+            "name": self.name,
+            "lat": (self.lat.to_degrees() * 1e6).round() / 1e6,
+            "lon": (self.lon.to_degrees() * 1e6).round() / 1e6,
+            "type": self.airfield_type.as_str(),
+            "country": self.country,
+        });
+
+        if let Some(elevation) = self.elevation {
+            item["elevation"] = json!(elevation);
+        }
+        if self.capture_radius != AF_CAPTURE_RADIUS {
+            item["radius"] = json!(self.capture_radius);
+        }
+        if self.boundary.len() > 0 {
+            item["boundary"] = json!(self.boundary.iter().map(|(lat, lon)| vec![*lat, *lon]).collect::<Vec<Vec<f64>>>());
+        }
+        if self.runways.len() > 0 {
+            item["runways"] = json!(self.runways.iter().map(|rw| json!({"name": rw.name, "lat": rw.lat, "lon": rw.lon, "heading": rw.heading})).collect::<Vec<Value>>());
+        }
+
+        item
+    }
+
+    /// Distance up to which the airfield may capture an event - the radius or the farthest boundary point.
+    /// @return [km]
+    pub fn reach(&self) -> f64 {
+        if self.boundary.len() == 0 {
+            return self.capture_radius;
+        }
+
+        self.boundary.iter()
+            .map(|(lat, lon)| AirfieldManager::get_distance_in_km(self.lat, self.lon, lat.to_radians(), lon.to_radians()))
+            .fold(0_f64, f64::max)
+    }
+
+    /// @param lat, lon: [deg]
+    /// @param dist: [km] distance of the point from the airfield reference point
+    pub fn captures(&self, lat: f64, lon: f64, dist: f64) -> bool {
+        if self.boundary.len() == 0 {
+            return dist <= self.capture_radius;
+        }
+
+        // ray casting; airfields are small enough to do it in plain lat/lon:
+        let mut inside = false;
+        let n = self.boundary.len();
+        for i in 0..n {
+            let (lat1, lon1) = self.boundary[i];
+            let (lat2, lon2) = self.boundary[(i + 1) % n];
+            if (lat1 > lat) != (lat2 > lat) && lon < lon1 + (lat - lat1) / (lat2 - lat1) * (lon2 - lon1) {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+}
+
+impl fmt::Display for AirfieldRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#AirfieldRecord: {0}; lat:{1:.4}; lon:{2:.4}", self.code, self.lat.to_degrees(), self.lon.to_degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AirfieldRecord, AirfieldType, AF_CAPTURE_RADIUS};
+
+    #[test]
+    fn boundary_takes_precedence_over_radius() {
+        let strip = AirfieldRecord::from_json(&json!({
+            "code": "LKTEST", "lat": 49.0, "lon": 16.0, "type": "ultralight", "elevation": 200,
+            "boundary": [[48.995, 15.99], [48.995, 16.01], [49.005, 16.01], [49.005, 15.99]],
+        })).unwrap();
+
+        assert_eq!(strip.airfield_type, AirfieldType::UltralightStrip);
+        assert_eq!(strip.elevation, Some(200));
+        assert_eq!(strip.capture_radius, AF_CAPTURE_RADIUS);
+        assert!(strip.reach() < 1.0);
+
+        assert!(strip.captures(49.001, 16.005, 0.4));
+        assert!(!strip.captures(49.01, 16.0, 1.1));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut airfield = AirfieldRecord::new("LKKA", 49.0294, 17.4397);
+        airfield.name = "Kunovice".into();
+        airfield.capture_radius = 3.5;
+
+        let loaded = AirfieldRecord::from_json(&airfield.as_json()).unwrap();
+        assert_eq!(loaded.code, "LKKA");
+        assert_eq!(loaded.name, "Kunovice");
+        assert_eq!(loaded.capture_radius, 3.5);
+        assert_eq!(loaded.elevation, None);
+        assert!(loaded.captures(49.03, 17.44, 3.0));
+        assert!(!loaded.captures(49.03, 17.44, 4.0));
+    }
+}
@@ -0,0 +1,127 @@
+//! Airfield lists shared by all workers and cron jobs. Each list is loaded once and reloaded
+//! when its file changes; the readers keep using the previous list until the new one is ready.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+
+use crate::airfield_manager::AirfieldManager;
+use crate::configuration::{get_airfields_filepath, HELIPORTS_FILEPATH, LAUNCH_SITES_FILEPATH};
+
+pub const AFR_RUN_INTERVAL: u64 = 60;   // [s] how often the files are checked for modification
+const MAX_LOGGED_CODES: usize = 20;
+
+lazy_static! {
+    static ref AIRFIELDS: AirfieldService = AirfieldService::new(&get_airfields_filepath(), false);
+    static ref LAUNCH_SITES: AirfieldService = AirfieldService::new(LAUNCH_SITES_FILEPATH, true);
+    static ref HELIPORTS: AirfieldService = AirfieldService::new(HELIPORTS_FILEPATH, true);
+}
+
+pub struct AirfieldService {
+    filepath: String,
+    optional: bool,     // the file does not need to exist
+    current: RwLock<Option<Arc<AirfieldManager>>>,
+    mtime: Mutex<Option<SystemTime>>,
+}
+
+impl AirfieldService {
+    fn new(filepath: &str, optional: bool) -> AirfieldService {
+        let service = AirfieldService {
+            filepath: filepath.into(),
+            optional,
+            current: RwLock::new(None),
+            mtime: Mutex::new(None),
+        };
+
+        if !optional || Path::new(filepath).exists() {
+            // the mandatory list must be readable on start:
+            let manager = AirfieldManager::new(filepath);
+            *service.mtime.lock().unwrap() = service.modified();
+            *service.current.write().unwrap() = Some(Arc::new(manager));
+        }
+
+        service
+    }
+
+    pub fn get(&self) -> Option<Arc<AirfieldManager>> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.filepath).and_then(|m| m.modified()).ok()
+    }
+
+    /// Reloads the list if the file has been modified (or appeared / vanished) since the last load.
+    pub fn reload_if_modified(&self) {
+        let mtime = self.modified();
+        if *self.mtime.lock().unwrap() == mtime {
+            return;
+        }
+
+        self.reload();
+    }
+
+    /// Reloads the list; a broken file keeps the current list in place.
+    pub fn reload(&self) {
+        let mtime = self.modified();
+
+        if mtime.is_none() && self.optional {
+            if self.current.write().unwrap().take().is_some() {
+                info!("Airfields file '{}' removed, list dropped", self.filepath);
+            }
+            *self.mtime.lock().unwrap() = None;
+            return;
+        }
+
+        let manager = match AirfieldManager::load(&self.filepath) {
+            Ok(manager) => manager,
+            Err(e) => {
+                error!("Keeping the current airfields: {e}");
+                *self.mtime.lock().unwrap() = mtime;   // do not retry until the file changes again
+                return;
+            }
+        };
+
+        match self.get() {
+            Some(old) => {
+                let (added, removed, changed) = old.diff(&manager);
+                let list = |codes: &Vec<String>| {
+                    let suffix = if codes.len() > MAX_LOGGED_CODES { ", .." } else { "" };
+                    format!("{}{suffix}", codes.iter().take(MAX_LOGGED_CODES).cloned().collect::<Vec<String>>().join(", "))
+                };
+                info!("Reloaded {} airfields from '{}': added {} [{}], removed {} [{}], changed {} [{}]",
+                    manager.len(), self.filepath, added.len(), list(&added), removed.len(), list(&removed), changed.len(), list(&changed));
+            },
+            None => info!("Loaded {} airfields from '{}'", manager.len(), self.filepath),
+        }
+
+        *self.current.write().unwrap() = Some(Arc::new(manager));
+        *self.mtime.lock().unwrap() = mtime;
+    }
+}
+
+/// The main airfield list.
+pub fn airfields() -> Arc<AirfieldManager> {
+    AIRFIELDS.get().expect("Airfields not loaded!")
+}
+
+/// Hang-gliding & paragliding sites (optional).
+pub fn launch_sites() -> Option<Arc<AirfieldManager>> {
+    LAUNCH_SITES.get()
+}
+
+/// Heliports & hospital helipads (optional).
+pub fn heliports() -> Option<Arc<AirfieldManager>> {
+    HELIPORTS.get()
+}
+
+/// Called periodically; touching a file is enough to get it reloaded.
+pub fn reload_if_modified() {
+    AIRFIELDS.reload_if_modified();
+    LAUNCH_SITES.reload_if_modified();
+    HELIPORTS.reload_if_modified();
+}
@@ -0,0 +1,349 @@
+//! Readers of third-party airfield databases - SeeYou CUP waypoint files and OpenAIP airport exports.
+//! They normalise the data into AirfieldRecords which can be merged into one list and written out
+//! in the json format the AirfieldManager reads.
+
+use std::fs;
+
+use log::{info, warn};
+use serde_json::Value;
+
+use crate::airfield_manager::airfield_record::{AirfieldRecord, AirfieldType, Runway};
+use crate::airfield_manager::kd_tree::KdTree;
+
+const DEDUPE_DISTANCE: f64 = 0.5;  // [km] airfields closer than this are considered the same one
+const MAX_CODE_LEN: usize = 16;     // for the codes made up of airfield names
+
+/// Loads airfields from a file of any supported format:
+///  *.cup - SeeYou waypoints (airfields only)
+///  *.json - our own airfield list or an OpenAIP airport export (array or {"items": [..]})
+pub fn load_file(filepath: &str) -> Result<Vec<AirfieldRecord>, String> {
+    let data = fs::read_to_string(filepath).map_err(|e| format!("Could not read '{filepath}': {e}"))?;
+
+    if filepath.to_lowercase().ends_with(".cup") {
+        return Ok(parse_cup(&data));
+    }
+
+    let json: Value = serde_json::from_str(&data).map_err(|e| format!("Could not parse json from '{filepath}': {e}"))?;
+    let items = match json["items"].as_array() {
+        Some(items) => items,
+        None => json.as_array().ok_or(format!("Array of airfields expected in '{filepath}'"))?,
+    };
+
+    let mut airfields = Vec::new();
+    for item in items {
+        let ar = if item["geometry"].is_object() { parse_openaip_item(item) } else { AirfieldRecord::from_json(item) };
+        match ar {
+            Some(ar) => airfields.push(ar),
+            None => warn!("Invalid airfield record in '{filepath}': {item}"),
+        }
+    }
+
+    Ok(airfields)
+}
+
+/// Merges airfields from several sources into one json file.
+/// Records of the earlier sources take precedence, the later ones only fill in what is missing.
+pub fn import(sources: &[String], out_filepath: &str) -> std::io::Result<()> {
+    let mut airfields = Vec::new();
+    for filepath in sources {
+        let loaded = load_file(filepath).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        info!("Loaded {} airfields from '{filepath}'", loaded.len());
+        airfields.extend(loaded);
+    }
+
+    let airfields = dedupe(airfields);
+    let json = Value::Array(airfields.iter().map(|af| af.as_json()).collect());
+    fs::write(out_filepath, serde_json::to_string_pretty(&json).unwrap())?;
+    info!("Written {} airfields into '{out_filepath}'", airfields.len());
+
+    Ok(())
+}
+
+fn is_icao_code(code: &str) -> bool {
+    code.len() == 4 && code.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// A code for airfields without one - uppercase letters & digits of the name.
+fn make_code(name: &str) -> String {
+    name.to_uppercase().chars().filter(|c| c.is_ascii_alphanumeric()).take(MAX_CODE_LEN).collect()
+}
+
+/// Fills in the details missing in the airfield.
+fn merge(into: &mut AirfieldRecord, other: &AirfieldRecord) {
+    if into.name.is_empty() { into.name = other.name.clone(); }
+    if into.elevation.is_none() { into.elevation = other.elevation; }
+    if into.airfield_type == AirfieldType::Unknown { into.airfield_type = other.airfield_type; }
+    if into.country.is_empty() { into.country = other.country.clone(); }
+    if into.boundary.is_empty() { into.boundary = other.boundary.clone(); }
+    if into.runways.is_empty() { into.runways = other.runways.clone(); }
+    if !is_icao_code(&into.code) && is_icao_code(&other.code) { into.code = other.code.clone(); }
+}
+
+/// Merges records of the same airfield - having the same ICAO code or being too close to each other.
+/// @param airfields: ordered by precedence
+pub fn dedupe(airfields: Vec<AirfieldRecord>) -> Vec<AirfieldRecord> {
+    let coords = airfields.iter().map(|af| (af.lat.to_degrees(), af.lon.to_degrees())).collect();
+    let index = KdTree::new(&coords);
+
+    let mut merged = vec![false; airfields.len()];
+    let mut result: Vec<AirfieldRecord> = Vec::new();
+
+    for i in 0..airfields.len() {
+        if merged[i] { continue; }
+
+        let mut ar = airfields[i].clone();
+        let (lat, lon) = coords[i];
+        for (j, _) in index.all_within(lat, lon, DEDUPE_DISTANCE) {
+            let other_icao = is_icao_code(&airfields[j].code) && is_icao_code(&ar.code) && airfields[j].code != ar.code;
+            if j > i && !merged[j] && !other_icao {
+                merge(&mut ar, &airfields[j]);
+                merged[j] = true;
+            }
+        }
+        if is_icao_code(&ar.code) {
+            for j in i + 1..airfields.len() {
+                if !merged[j] && airfields[j].code == ar.code {
+                    merge(&mut ar, &airfields[j]);
+                    merged[j] = true;
+                }
+            }
+        }
+
+        result.push(ar);
+    }
+
+    if result.len() < airfields.len() {
+        info!("Merged {} duplicate airfield records", airfields.len() - result.len());
+    }
+
+    result
+}
+
+/// Splits a CUP line into fields; fields may be quoted and contain commas.
+fn split_cup_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// "4916.383N" / "01612.583E" -> degrees
+fn parse_cup_coord(s: &str) -> Option<f64> {
+    if !s.is_ascii() { return None; }
+    let hemisphere = s.chars().last()?;
+    let num = &s[..s.len() - 1];
+    let deg_len = if hemisphere == 'N' || hemisphere == 'S' { 2 } else { 3 };
+    if num.len() <= deg_len { return None; }
+
+    let deg: f64 = num[..deg_len].parse().ok()?;
+    let min: f64 = num[deg_len..].parse().ok()?;
+    let val = deg + min / 60.0;
+
+    match hemisphere {
+        'N' | 'E' => Some(val),
+        'S' | 'W' => Some(-val),
+        _ => None,
+    }
+}
+
+/// "234.0m" / "800ft" -> [m]
+fn parse_cup_elevation(s: &str) -> Option<i32> {
+    if let Some(ft) = s.strip_suffix("ft") {
+        return ft.trim().parse::<f64>().ok().map(|ft| (ft * 0.3048).round() as i32);
+    }
+    s.trim_end_matches('m').trim().parse::<f64>().ok().map(|m| m.round() as i32)
+}
+
+/// Both directions of a runway given by its heading - the CUP does not provide the threshold
+/// positions so these are set to the airfield reference point (parallel runways cannot be told apart).
+fn runways_from_heading(lat: f64, lon: f64, heading: f64) -> Vec<Runway> {
+    let designator = |heading: f64| {
+        let num = ((heading / 10.0).round() as i32 + 35) % 36 + 1;
+        format!("{num:02}")
+    };
+
+    let reverse = (heading + 180.0) % 360.0;
+    vec![
+        Runway { name: designator(heading), lat, lon, heading },
+        Runway { name: designator(reverse), lat, lon, heading: reverse },
+    ]
+}
+
+/// SeeYou CUP waypoints; only the airfields (styles 2, 4 & 5) are taken.
+pub fn parse_cup(data: &str) -> Vec<AirfieldRecord> {
+    let mut lines = data.lines();
+    let header = match lines.next() {
+        Some(header) => split_cup_line(&header.to_lowercase()),
+        None => return Vec::new(),
+    };
+    let col = |name: &str| header.iter().position(|h| h == name);
+    let (name_col, code_col, country_col, lat_col, lon_col, elev_col, style_col, rwdir_col) =
+        (col("name"), col("code"), col("country"), col("lat"), col("lon"), col("elev"), col("style"), col("rwdir"));
+
+    let mut airfields = Vec::new();
+    for line in lines {
+        if line.starts_with("-----Related Tasks-----") { break; }
+        if line.trim().is_empty() { continue; }
+
+        let fields = split_cup_line(line);
+        let field = |col: Option<usize>| col.and_then(|i| fields.get(i)).map(|s| s.as_str()).unwrap_or("");
+
+        let airfield_type = match field(style_col) {
+            "2" | "5" => AirfieldType::Airport,     // grass / solid surface
+            "4" => AirfieldType::GliderSite,
+            _ => continue,
+        };
+
+        let (lat, lon) = match (parse_cup_coord(field(lat_col)), parse_cup_coord(field(lon_col))) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => {
+                warn!("Invalid coordinates in CUP line: {line}");
+                continue;
+            }
+        };
+
+        let name = field(name_col);
+        let code = if field(code_col).is_empty() { make_code(name) } else { field(code_col).to_uppercase() };
+        if code.is_empty() { continue; }
+
+        let mut ar = AirfieldRecord::new(&code, lat, lon);
+        ar.name = name.into();
+        ar.country = field(country_col).to_uppercase();
+        ar.airfield_type = airfield_type;
+        ar.elevation = parse_cup_elevation(field(elev_col));
+        if let Ok(heading) = field(rwdir_col).parse::<f64>() {
+            ar.runways = runways_from_heading(lat, lon, heading);
+        }
+
+        airfields.push(ar);
+    }
+
+    airfields
+}
+
+/// One airport of the OpenAIP (v2) export.
+fn parse_openaip_item(item: &Value) -> Option<AirfieldRecord> {
+    // https://docs.openaip.net - airport types:
+    let airfield_type = match item["type"].as_i64()? {
+        1 => AirfieldType::GliderSite,
+        6 | 11 | 12 => AirfieldType::UltralightStrip,
+        4 | 7 => AirfieldType::Heliport,
+        8 => return None,   // closed
+        _ => AirfieldType::Airport,
+    };
+
+    let coords = item["geometry"]["coordinates"].as_array()?;   // [lon, lat]
+    let (lon, lat) = (coords.get(0)?.as_f64()?, coords.get(1)?.as_f64()?);
+
+    let name = item["name"].as_str().unwrap_or("");
+    let code = match item["icaoCode"].as_str() {
+        Some(icao) if !icao.is_empty() => icao.to_uppercase(),
+        _ => make_code(name),
+    };
+    if code.is_empty() { return None; }
+
+    let mut ar = AirfieldRecord::new(&code, lat, lon);
+    ar.name = name.into();
+    ar.airfield_type = airfield_type;
+    ar.country = item["country"].as_str().unwrap_or("").to_uppercase();
+
+    let elevation = &item["elevation"];
+    ar.elevation = elevation["value"].as_f64().map(|val| {
+        if elevation["unit"].as_i64() == Some(1) { (val * 0.3048).round() as i32 } else { val.round() as i32 }  // 0 = m, 1 = ft
+    });
+
+    if let Some(runways) = item["runways"].as_array() {
+        for rw in runways {
+            if let (Some(designator), Some(heading)) = (rw["designator"].as_str(), rw["trueHeading"].as_f64()) {
+                // each direction comes as a separate record; no thresholds here either
+                if !ar.runways.iter().any(|r| r.name == designator) {
+                    ar.runways.push(Runway { name: designator.into(), lat, lon, heading });
+                }
+            }
+        }
+    }
+
+    Some(ar)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::airfield_manager::airfield_record::{AirfieldRecord, AirfieldType};
+
+    use super::{dedupe, parse_cup, parse_openaip_item};
+
+    #[test]
+    fn cup_airfields_are_parsed() {
+        let data = "name,code,country,lat,lon,elev,style,rwdir,rwlen,freq,desc\n\
+            \"Kunovice\",\"LKKU\",CZ,4901.767N,01726.383E,176.0m,5,030,2000.0m,\"122.800\",\"\"\n\
+            \"Hill, top\",\"HILL\",CZ,4910.000N,01630.000E,1500ft,7,,,,\"\"\n\
+            \"Strip\",,CZ,4905.500S,01615.250W,800ft,2,180,500m,,\n\
+            -----Related Tasks-----\n\
+            \"Task\",\"Kunovice\"";
+
+        let airfields = parse_cup(data);
+        assert_eq!(airfields.len(), 2);
+
+        let lkku = &airfields[0];
+        assert_eq!(lkku.code, "LKKU");
+        assert_eq!(lkku.elevation, Some(176));
+        assert_eq!(lkku.airfield_type, AirfieldType::Airport);
+        assert!((lkku.lat.to_degrees() - 49.02945).abs() < 1e-4);
+        assert_eq!(lkku.runways.iter().map(|rw| rw.name.as_str()).collect::<Vec<_>>(), vec!["03", "21"]);
+
+        let strip = &airfields[1];
+        assert_eq!(strip.code, "STRIP");
+        assert_eq!(strip.elevation, Some(244));
+        assert_eq!(strip.airfield_type, AirfieldType::Airport);
+        assert!(strip.lat < 0.0 && strip.lon < 0.0);
+        assert_eq!(strip.runways[1].name, "36");
+    }
+
+    #[test]
+    fn openaip_airport_is_parsed() {
+        let ar = parse_openaip_item(&json!({
+            "name": "Kunovice", "icaoCode": "LKKU", "type": 2, "country": "CZ",
+            "geometry": { "type": "Point", "coordinates": [17.4397, 49.0294] },
+            "elevation": { "value": 578, "unit": 1, "referenceDatum": 1 },
+            "runways": [{ "designator": "03", "trueHeading": 28 }, { "designator": "21", "trueHeading": 208 }],
+        })).unwrap();
+
+        assert_eq!(ar.code, "LKKU");
+        assert_eq!(ar.elevation, Some(176));
+        assert!((ar.lat.to_degrees() - 49.0294).abs() < 1e-6);
+        assert_eq!(ar.runways.len(), 2);
+
+        assert!(parse_openaip_item(&json!({ "name": "Closed", "type": 8, "geometry": { "coordinates": [17.0, 49.0] } })).is_none());
+    }
+
+    #[test]
+    fn duplicates_are_merged() {
+        let mut named = AirfieldRecord::new("KUNOVICE", 49.0294, 17.4397);
+        named.name = "Kunovice".into();
+        let mut icao = AirfieldRecord::new("LKKU", 49.0300, 17.4400);
+        icao.elevation = Some(176);
+        let mut far = AirfieldRecord::new("LKKU", 49.1, 17.5);
+        far.country = "CZ".into();
+        let other = AirfieldRecord::new("LKUL", 49.0305, 17.4405);
+
+        let airfields = dedupe(vec![named, icao, far, other]);
+        assert_eq!(airfields.len(), 2);
+        assert_eq!(airfields[0].code, "LKKU");
+        assert_eq!(airfields[0].name, "Kunovice");
+        assert_eq!(airfields[0].elevation, Some(176));
+        assert_eq!(airfields[0].country, "CZ");
+        assert_eq!(airfields[1].code, "LKUL");
+    }
+}
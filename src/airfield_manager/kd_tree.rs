@@ -0,0 +1,229 @@
+/**
+ * 3D k-d tree over points on the unit sphere. Working with unit vectors instead of lat/lon
+ * means no special cases near the poles, the antimeridian or the equator - the straight
+ * (chord) distance between two unit vectors grows monotonically with the great-circle distance.
+ */
+
+const EARTH_RADIUS: f64 = 6371.0;   // [km]
+
+#[derive(Debug, Clone)]
+struct Node {
+    point: [f64; 3],
+    index: usize,   // index of the item in the caller's list
+}
+
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    nodes: Vec<Node>,   // implicit tree - the median of each sub-slice is its root
+}
+
+/// lat, lon in degrees
+pub fn to_unit_vector(lat: f64, lon: f64) -> [f64; 3] {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn km_to_chord(dist: f64) -> f64 {
+    2.0 * (dist.min(EARTH_RADIUS * std::f64::consts::PI) / (2.0 * EARTH_RADIUS)).sin()
+}
+
+fn chord_to_km(chord: f64) -> f64 {
+    2.0 * EARTH_RADIUS * (chord / 2.0).min(1.0).asin()
+}
+
+impl KdTree {
+    /// @param coords: (lat, lon) in degrees; the queries return indices into this list
+    pub fn new(coords: &Vec<(f64, f64)>) -> KdTree {
+        let mut nodes: Vec<Node> = coords.iter().enumerate()
+            .map(|(index, (lat, lon))| Node { point: to_unit_vector(*lat, *lon), index })
+            .collect();
+
+        KdTree::build(&mut nodes, 0);
+
+        KdTree { nodes }
+    }
+
+    fn build(nodes: &mut [Node], depth: usize) {
+        if nodes.len() <= 1 {
+            return;
+        }
+
+        let axis = depth % 3;
+        let mid = nodes.len() / 2;
+        nodes.select_nth_unstable_by(mid, |a, b| a.point[axis].total_cmp(&b.point[axis]));
+
+        let (left, right) = nodes.split_at_mut(mid);
+        KdTree::build(left, depth + 1);
+        KdTree::build(&mut right[1..], depth + 1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// @param radius: [km]
+    /// @return (index, distance in km) of the nearest point not further than the radius
+    pub fn nearest_within(&self, lat: f64, lon: f64, radius: f64) -> Option<(usize, f64)> {
+        let query = to_unit_vector(lat, lon);
+        let mut best: Option<usize> = None;
+        let mut best_dist2 = km_to_chord(radius).powi(2);
+
+        self.search_nearest(0, self.nodes.len(), 0, &query, &mut best, &mut best_dist2);
+
+        best.map(|i| (self.nodes[i].index, chord_to_km(best_dist2.sqrt())))
+    }
+
+    fn search_nearest(&self, lo: usize, hi: usize, depth: usize, query: &[f64; 3], best: &mut Option<usize>, best_dist2: &mut f64) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let node = &self.nodes[mid];
+
+        let dist2 = squared_distance(&node.point, query);
+        if dist2 <= *best_dist2 {
+            *best = Some(mid);
+            *best_dist2 = dist2;
+        }
+
+        let axis = depth % 3;
+        let diff = query[axis] - node.point[axis];
+        let (near, far) = if diff < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+
+        self.search_nearest(near.0, near.1, depth + 1, query, best, best_dist2);
+        if diff * diff <= *best_dist2 {
+            self.search_nearest(far.0, far.1, depth + 1, query, best, best_dist2);
+        }
+    }
+
+    /// @param radius: [km]
+    /// @return (index, distance in km) of all points not further than the radius, the nearest first
+    pub fn all_within(&self, lat: f64, lon: f64, radius: f64) -> Vec<(usize, f64)> {
+        let query = to_unit_vector(lat, lon);
+        let radius2 = km_to_chord(radius).powi(2);
+
+        let mut found: Vec<(usize, f64)> = Vec::new();
+        self.search_all(0, self.nodes.len(), 0, &query, radius2, &mut found);
+
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found.into_iter().map(|(i, dist2)| (self.nodes[i].index, chord_to_km(dist2.sqrt()))).collect()
+    }
+
+    fn search_all(&self, lo: usize, hi: usize, depth: usize, query: &[f64; 3], radius2: f64, found: &mut Vec<(usize, f64)>) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let node = &self.nodes[mid];
+
+        let dist2 = squared_distance(&node.point, query);
+        if dist2 <= radius2 {
+            found.push((mid, dist2));
+        }
+
+        let axis = depth % 3;
+        let diff = query[axis] - node.point[axis];
+        if diff < 0.0 || diff * diff <= radius2 {
+            self.search_all(lo, mid, depth + 1, query, radius2, found);
+        }
+        if diff >= 0.0 || diff * diff <= radius2 {
+            self.search_all(mid + 1, hi, depth + 1, query, radius2, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KdTree;
+    use crate::airfield_manager::AirfieldManager;
+
+    /// xorshift - deterministic & good enough to scatter test points
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn range(&mut self, from: f64, to: f64) -> f64 {
+            from + (to - from) * self.next()
+        }
+    }
+
+    fn distance(a: &(f64, f64), b: &(f64, f64)) -> f64 {
+        AirfieldManager::get_distance_in_km(a.0.to_radians(), a.1.to_radians(), b.0.to_radians(), b.1.to_radians())
+    }
+
+    /// Points around the tricky places (poles, antimeridian, equator & greenwich) plus some spread all over.
+    fn random_points(rng: &mut Rng, n: usize) -> Vec<(f64, f64)> {
+        let centers = [(89.9, 0.0), (-89.9, 120.0), (0.0, 180.0), (65.0, -179.9), (0.0, 0.0), (49.0, 16.0)];
+        (0..n).map(|i| {
+            if i % 4 == 0 {
+                (rng.range(-90.0, 90.0), rng.range(-180.0, 180.0))
+            } else {
+                let (lat, lon) = centers[i % centers.len()];
+                let lat: f64 = (lat + rng.range(-0.5, 0.5)).clamp(-90.0, 90.0);
+                let mut lon = lon + rng.range(-0.5, 0.5);
+                if lon > 180.0 { lon -= 360.0; }
+                if lon < -180.0 { lon += 360.0; }
+                (lat, lon)
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        let points = random_points(&mut rng, 2000);
+        let tree = KdTree::new(&points);
+        assert_eq!(tree.len(), points.len());
+
+        for query in random_points(&mut rng, 500) {
+            for radius in [1.0, 8.0, 50.0, 500.0] {
+                let brute: Vec<(usize, f64)> = points.iter().enumerate()
+                    .map(|(i, p)| (i, distance(&query, p)))
+                    .filter(|(_, d)| *d <= radius)
+                    .collect();
+
+                // nearest:
+                let brute_nearest = brute.iter().map(|(_, d)| *d).fold(f64::MAX, f64::min);
+                match tree.nearest_within(query.0, query.1, radius) {
+                    Some((i, d)) => {
+                        assert!((d - distance(&query, &points[i])).abs() < 1e-3, "{query:?} r={radius}");
+                        assert!((d - brute_nearest).abs() < 1e-3, "{query:?} r={radius}: {d} vs {brute_nearest}");
+                    },
+                    None => assert!(brute.iter().all(|(_, d)| *d > radius - 1e-3), "{query:?} r={radius}"),
+                }
+
+                // all within (ignoring points right on the circle):
+                let found = tree.all_within(query.0, query.1, radius);
+                for (i, d) in brute.iter().filter(|(_, d)| *d < radius - 1e-3) {
+                    assert!(found.iter().any(|(j, _)| j == i), "{query:?} r={radius}: missing {i} at {d} km");
+                }
+                for (i, d) in found.iter() {
+                    assert!(distance(&query, &points[*i]) < radius + 1e-3, "{query:?} r={radius}: extra {i} at {d} km");
+                }
+                assert!(found.windows(2).all(|w| w[0].1 <= w[1].1));
+            }
+        }
+    }
+
+    #[test]
+    fn across_the_antimeridian() {
+        let points = vec![(-16.5, 179.99), (-16.5, 170.0)];
+        let tree = KdTree::new(&points);
+
+        let (i, d) = tree.nearest_within(-16.5, -179.99, 8.0).unwrap();
+        assert_eq!(i, 0);
+        assert!(d < 3.0);
+    }
+}
@@ -0,0 +1,113 @@
+use log::{error, info};
+use serde_json::json;
+
+use ogn_client::data_structures::{AddressType, AircraftType};
+
+use crate::configuration::{get_alert_webhook_url, get_mqtt_alert_topic, get_mqtt_outlanding_topic};
+use crate::db::track_store::TrackPoint;
+use crate::mqtt::MqttMessage;
+
+/// Links to a map centered on given coordinates.
+/// @return (google maps url, openstreetmap url)
+pub fn map_links(lat: f64, lon: f64) -> (String, String) {
+    (
+        format!("https://www.google.com/maps?q={lat:.5},{lon:.5}"),
+        format!("https://www.openstreetmap.org/?mlat={lat:.5}&mlon={lon:.5}#map=14/{lat:.5}/{lon:.5}"),
+    )
+}
+
+/// A landing away from any known airfield.
+#[derive(Debug, Clone)]
+pub struct OutlandingAlert {
+    pub ts: i64,
+    pub address: String,
+    pub address_type: AddressType,
+    pub aircraft_type: AircraftType,
+    pub lat: f64,
+    pub lon: f64,
+    pub elevation: Option<i64>, // [m] terrain elevation at the landing spot
+}
+
+impl OutlandingAlert {
+    pub fn as_json(&self) -> String {
+        let (google_link, osm_link) = map_links(self.lat, self.lon);
+
+        json!({
+            "ts": self.ts,
+            "address": self.address,
+            "addressType": self.address_type.as_short_str(),
+            "aircraftType": self.aircraft_type.value(),
+            "lat": (self.lat * 100000.0).round() / 100000.0,
+            "lon": (self.lon * 100000.0).round() / 100000.0,
+            "elevation": self.elevation,
+            "link": google_link,
+            "osmLink": osm_link,
+        }).to_string()
+    }
+
+    pub fn as_mqtt_message(&self) -> MqttMessage {
+        MqttMessage { topic: get_mqtt_outlanding_topic(), payload: self.as_json() }
+    }
+}
+
+/// An airborne aircraft which stopped transmitting in a suspicious situation
+/// (away from any airfield, low above the ground or descending fast).
+#[derive(Debug, Clone)]
+pub struct LostContactAlert {
+    pub address: String,
+    pub address_type: AddressType,
+    pub reason: String,
+    pub positions: Vec<TrackPoint>, // last known positions, the latest one first
+}
+
+impl LostContactAlert {
+    pub fn as_json(&self) -> String {
+        let positions: Vec<serde_json::Value> = self.positions.iter().map(|p| json!({
+            "ts": p.ts,
+            "lat": p.lat,
+            "lon": p.lon,
+            "alt": p.alt,
+            "agl": p.agl,
+            "gs": p.gs,
+            "vs": p.vs,
+        })).collect();
+
+        let (google_link, osm_link) = match self.positions.first() {
+            Some(p) => map_links(p.lat, p.lon),
+            None => ("".into(), "".into()),
+        };
+
+        json!({
+            "address": self.address,
+            "addressType": self.address_type.as_short_str(),
+            "reason": self.reason,
+            "ts": self.positions.first().map(|p| p.ts),
+            "link": google_link,
+            "osmLink": osm_link,
+            "positions": positions,
+        }).to_string()
+    }
+
+    pub fn as_mqtt_message(&self) -> MqttMessage {
+        MqttMessage { topic: get_mqtt_alert_topic(), payload: self.as_json() }
+    }
+
+    /// POSTs the alert to ALERT_WEBHOOK_URL (if configured).
+    pub fn send_to_webhook(&self) {
+        let url = match get_alert_webhook_url() {
+            Some(url) => url,
+            None => return,
+        };
+
+        let client = reqwest::blocking::Client::new();
+        let res = client.post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(self.as_json())
+            .send();
+
+        match res {
+            Ok(resp) => info!("Lost-contact alert for {} posted to webhook: {}", self.address, resp.status()),
+            Err(e) => error!("Unable to post lost-contact alert to '{url}': {e}"),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Source of the current time. Live processing follows the wall clock,
+//! replay of recorded traffic follows the timestamps of the replayed beacons.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use chrono::Utc;
+
+pub trait Clock: Send + Sync {
+    /// @return UTC timestamp [ms]
+    fn now_millis(&self) -> i64;
+
+    /// @return UTC timestamp [s]
+    fn now(&self) -> i64 {
+        self.now_millis() / 1000
+    }
+}
+
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        Utc::now().timestamp_millis()
+    }
+}
+
+/// Clock which moves only when told to.
+pub struct SimulatedClock {
+    ts_millis: AtomicI64,
+}
+
+impl SimulatedClock {
+    /// @param ts: UTC [s]
+    pub fn new(ts: i64) -> SimulatedClock {
+        Self {
+            ts_millis: AtomicI64::new(ts * 1000),
+        }
+    }
+
+    /// Moves the clock forward to given time; never backwards.
+    /// @param ts: UTC [s]
+    pub fn advance_to(&self, ts: i64) {
+        self.ts_millis.fetch_max(ts * 1000, Ordering::Relaxed);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now_millis(&self) -> i64 {
+        self.ts_millis.load(Ordering::Relaxed)
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{airfield_manager::AirfieldManager, configuration::AIRFIELDS_FILEPATH};
+use std::sync::Arc;
+
+use crate::airfield_manager::airfield_service::{self, AFR_RUN_INTERVAL};
+use crate::clock::Clock;
+use crate::detection_profiles::{self, DPR_RUN_INTERVAL};
 
 use self::periodic_timer::PeriodicTimer;
 
@@ -11,9 +15,16 @@ mod real_takeoff_lookup;
 use real_takeoff_lookup::{RealTakeoffLookup, RTL_RUN_INTERVAL};
 mod redis_reaper;
 use redis_reaper::{RedisReaper, RR_RUN_INTERVAL};
+mod tow_lookup;
+use tow_lookup::{TowLookup, TL_RUN_INTERVAL};
+mod launch_classifier;
+use launch_classifier::{LaunchClassifier, LC_RUN_INTERVAL};
+mod runway_lookup;
+use runway_lookup::{RunwayLookup, RWL_RUN_INTERVAL};
 
 pub struct CronJobs {
     jobs: Vec<PeriodicTimer>,
+    clock: Arc<dyn Clock>,
 }
 
 pub trait CronJob {
@@ -21,9 +32,10 @@ pub trait CronJob {
 }
 
 impl CronJobs {
-    pub fn new() -> CronJobs {
+    pub fn new(clock: Arc<dyn Clock>) -> CronJobs {
         CronJobs {
             jobs: Vec::new(),
+            clock,
         }
     }
 
@@ -34,30 +46,61 @@ impl CronJobs {
         // t.start();
         // self.jobs.push(t);
 
-        // tl = TowLookup()
-        // self.towLookupTimer = PeriodicTimer(TowLookup.RUN_INTERVAL, tl.gliderTowLookup)
-        // self.towLookupTimer.start()
+        let mut tow_lookup_job = PeriodicTimer::new(
+            "Tow Lookup".into(), 
+            TL_RUN_INTERVAL, 
+            { let clock = Arc::clone(&self.clock); move || TowLookup::glider_tow_lookup(clock.as_ref()) });
+        tow_lookup_job.start();
+        self.jobs.push(tow_lookup_job);
+
+        let mut launch_classifier_job = PeriodicTimer::new(
+            "Launch Classifier".into(), 
+            LC_RUN_INTERVAL, 
+            { let clock = Arc::clone(&self.clock); move || LaunchClassifier::classify_launches(clock.as_ref()) });
+        launch_classifier_job.start();
+        self.jobs.push(launch_classifier_job);
 
         let mut redis_reaper_job = PeriodicTimer::new(
             "Redis Reaper".into(), 
             RR_RUN_INTERVAL, 
-            RedisReaper::do_work);
+            { let clock = Arc::clone(&self.clock); move || RedisReaper::do_work(clock.as_ref()) });
             redis_reaper_job.start();
         self.jobs.push(redis_reaper_job);
 
         let mut dist_calc_job = PeriodicTimer::new(
             "Flown Distance Calculator".into(), 
             FDC_RUN_INTERVAL, 
-            FlownDistanceCalculator::calc_distances);
+            { let clock = Arc::clone(&self.clock); move || FlownDistanceCalculator::calc_distances(clock.as_ref()) });
         dist_calc_job.start();
         self.jobs.push(dist_calc_job);
 
         let mut dist_calc_job = PeriodicTimer::new(
             "Real Take-off Lookup".into(), 
             RTL_RUN_INTERVAL, 
-            RealTakeoffLookup::check_takeoffs);
+            { let clock = Arc::clone(&self.clock); move || RealTakeoffLookup::check_takeoffs(clock.as_ref()) });
         dist_calc_job.start();
         self.jobs.push(dist_calc_job);
+
+        let mut runway_lookup_job = PeriodicTimer::new(
+            "Runway Lookup".into(), 
+            RWL_RUN_INTERVAL, 
+            { let clock = Arc::clone(&self.clock); move || RunwayLookup::find_runways(clock.as_ref()) });
+        runway_lookup_job.start();
+        self.jobs.push(runway_lookup_job);
+
+        let mut airfield_reload_job = PeriodicTimer::new(
+            "Airfield Reloader".into(), 
+            AFR_RUN_INTERVAL, 
+            airfield_service::reload_if_modified);
+        airfield_reload_job.start();
+        self.jobs.push(airfield_reload_job);
+
+        let mut profiles_reload_job = PeriodicTimer::new(
+            "Detection Profiles Reloader".into(), 
+            DPR_RUN_INTERVAL, 
+            detection_profiles::reload_if_modified);
+        profiles_reload_job.start();
+        self.jobs.push(profiles_reload_job);
         
         // eventWatcher = EventWatcher()
         // self.eventWatcherTimer = PeriodicTimer(EventWatcher.RUN_INTERVAL, eventWatcher.processEvents)
@@ -0,0 +1,164 @@
+use log::{info, warn, error};
+
+use ogn_client::data_structures::AircraftType;
+
+use crate::clock::Clock;
+use crate::db::data_structures::{LaunchMethod, LogbookItem};
+use crate::db::track_store::{self, TrackPoint};
+use crate::db::logbook_repository::{self, LogbookRepository};
+
+pub const LC_RUN_INTERVAL: u64 = 60;    // [s]
+const LC_WINDOW: i64 = 120;             // [s] portion of the flight after take-off used for the classification
+const LC_LAUNCH_WINDOW: i64 = 60;       // [s] climb profile of winch & bungee launches
+const LC_WINCH_MIN_ALT_GAIN: i64 = 150; // [m]
+const LC_WINCH_MIN_VS: f64 = 7.0;       // [m/s]
+const LC_BUNGEE_MAX_ALT_GAIN: i64 = 30; // [m]
+const LC_BUNGEE_MAX_GS: i64 = 80;       // [km/h]
+const LC_MIN_POINTS: usize = 3;
+
+pub struct LaunchClassifier {}
+
+impl LaunchClassifier {
+
+    /// Lists flights without launch method; gliders only once their tow pairing (see TowLookup) has been settled.
+    fn list_unclassified_entries(ts: i64, repository: &mut Box<dyn LogbookRepository>) -> Vec<LogbookItem> {
+        repository.list_unclassified_entries(ts - 24 * 3600, ts, 100)
+            .unwrap_or_else(|e| { error!("{e}"); vec![] })
+            .into_iter()
+            .filter(|item| item.aircraft_type != AircraftType::Glider || item.tow_checked)
+            .collect()
+    }
+
+    /// Altitude gained within the first `window` seconds after the first position.
+    /// @return (altitude gain [m], max vertical speed [m/s], max ground speed [km/h])
+    fn climb_profile(track: &Vec<TrackPoint>, window: i64) -> (i64, f64, i64) {
+        let start = &track[0];
+
+        let mut alt_gain = 0_i64;
+        let mut max_vs = 0_f64;
+        let mut max_gs = 0_i64;
+        for p in track.iter().take_while(|p| p.ts - start.ts <= window) {
+            alt_gain = alt_gain.max(p.alt - start.alt);
+            max_vs = max_vs.max(p.vs);
+            max_gs = max_gs.max(p.gs);
+        }
+
+        (alt_gain, max_vs, max_gs)
+    }
+
+    /// Classifies the launch from the first LC_WINDOW seconds of the flight.
+    /// A winch launch is a short & steep climb, a bungee launch barely gains any height at low speed,
+    /// an aerotow is a glider paired with a tow plane and anything else launched by itself.
+    /// The tow plane of a pair (tow_id is set on both entries) is self-launched.
+    pub fn classify(aircraft_type: &AircraftType, towed: bool, track: &Vec<TrackPoint>) -> LaunchMethod {
+        if *aircraft_type != AircraftType::Glider {
+            return LaunchMethod::SelfLaunch;
+        }
+
+        if towed {
+            return LaunchMethod::Aerotow;
+        }
+
+        if track.len() < LC_MIN_POINTS {
+            return LaunchMethod::Unknown;
+        }
+
+        let (alt_gain, max_vs, max_gs) = LaunchClassifier::climb_profile(track, LC_LAUNCH_WINDOW);
+        if alt_gain >= LC_WINCH_MIN_ALT_GAIN && max_vs >= LC_WINCH_MIN_VS {
+            return LaunchMethod::Winch;
+        }
+
+        if alt_gain <= LC_BUNGEE_MAX_ALT_GAIN && max_gs <= LC_BUNGEE_MAX_GS {
+            return LaunchMethod::Bungee;
+        }
+
+        LaunchMethod::SelfLaunch
+    }
+
+    pub fn classify_launches(clock: &dyn Clock) {
+        let mut repository = match logbook_repository::get_logbook_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                warn!("Could not obtain db connection, skipping classify_launches(): {e}");
+                return;
+            }
+        };
+
+        let mut track_store = match track_store::get_track_store() {
+            Some(track_store) => track_store,
+            None => return,
+        };
+
+        let ts = clock.now();
+        let entries = LaunchClassifier::list_unclassified_entries(ts, &mut repository);
+
+        for item in entries.iter() {
+            let addr = format!("{}{}", item.addr_type.as_long_str(), item.addr);
+            let track = track_store.get_track(&addr, item.takeoff_ts, item.takeoff_ts + LC_WINDOW);
+
+            let launch_method = LaunchClassifier::classify(&item.aircraft_type, item.tow_id > 0, &track);
+
+            if let Err(e) = repository.set_launch_method(item.id, launch_method) {
+                error!("{e}");
+            }
+        }
+
+        if entries.len() > 0 {
+            info!("Num classified launches: {}", entries.len());
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use ogn_client::data_structures::AircraftType;
+
+    use crate::db::data_structures::LaunchMethod;
+    use crate::db::track_store::TrackPoint;
+
+    use super::LaunchClassifier;
+
+    /// Track with a position every 5 s; (alt [m], gs [km/h], vs [m/s]) per point.
+    fn track(points: &[(i64, i64, f64)]) -> Vec<TrackPoint> {
+        points.iter().enumerate()
+            .map(|(i, &(alt, gs, vs))| TrackPoint { ts: 1000 + 5 * i as i64, lat: 49.0, lon: 16.0, alt, agl: alt - 300, gs, vs })
+            .collect()
+    }
+
+    #[test]
+    fn winch() {
+        let track = track(&[(300, 60, 0.0), (340, 100, 12.0), (400, 110, 15.0), (460, 100, 14.0), (500, 90, 8.0), (510, 90, 1.0)]);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, false, &track), LaunchMethod::Winch);
+    }
+
+    #[test]
+    fn aerotow() {
+        let track = track(&[(300, 90, 0.0), (310, 110, 2.0), (325, 115, 3.0), (340, 115, 3.0), (355, 115, 3.0)]);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, true, &track), LaunchMethod::Aerotow);
+
+        // the same glider without a tow plane paired (yet):
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, false, &track), LaunchMethod::SelfLaunch);
+    }
+
+    #[test]
+    fn tow_plane() {
+        // tow_id is set on the tow plane entry as well:
+        let track = track(&[(300, 90, 0.0), (310, 110, 2.0), (325, 115, 3.0), (340, 115, 3.0), (355, 115, 3.0)]);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::TowPlane, true, &track), LaunchMethod::SelfLaunch);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::TowPlane, false, &track), LaunchMethod::SelfLaunch);
+    }
+
+    #[test]
+    fn bungee() {
+        let track = track(&[(500, 30, 0.0), (505, 45, 1.0), (510, 55, 0.5), (508, 60, -0.5), (505, 60, -0.5)]);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, false, &track), LaunchMethod::Bungee);
+    }
+
+    #[test]
+    fn too_few_points() {
+        let track = track(&[(300, 60, 0.0), (400, 110, 15.0)]);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, false, &track), LaunchMethod::Unknown);
+        assert_eq!(LaunchClassifier::classify(&AircraftType::Glider, false, &vec![]), LaunchMethod::Unknown);
+    }
+}
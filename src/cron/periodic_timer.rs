@@ -18,12 +18,12 @@ pub trait PeriodicTimerTask {
      interval: u64,  // [s]
      thread: Option<thread::JoinHandle<()>>,
      do_run: Arc<AtomicBool>,
-     handler: Arc<fn()>,
+     handler: Arc<dyn Fn() + Send + Sync>,
     //  task: Arc<dyn PeriodicTimerTask + Sync + Send + 'static>,
  }
  
  impl PeriodicTimer {
-     pub fn new(name: String, interval: u64, handler: fn()) -> PeriodicTimer {
+     pub fn new(name: String, interval: u64, handler: impl Fn() + Send + Sync + 'static) -> PeriodicTimer {
          PeriodicTimer {
              name,
              interval,
@@ -1,20 +1,12 @@
 
-use chrono::Utc;
 use log::{info, warn, error};
-use mysql::Row;
-use mysql::prelude::Queryable;
-use rinfluxdb::influxql::blocking::Client;
-use rinfluxdb::influxql::Query;
-use rinfluxdb_influxql::ClientError;
-use url::Url;
-
-use ogn_client::data_structures::AddressType;
-
-use crate::airfield_manager::AirfieldManager;
-use crate::configuration::{AIRFIELDS_FILEPATH, INFLUX_SERIES_NAME, get_influx_url, get_influx_db_name};
-use crate::db::mysql::MySQL;
-use crate::db::dataframe::{Column, DataFrame};
+
+use crate::airfield_manager::airfield_service;
+use crate::clock::Clock;
+use crate::db::logbook_repository::{self, LogbookRepository};
 use crate::db::data_structures::LogbookItem;
+use crate::db::track_store::{self, TrackPoint};
+use crate::detection_profiles;
 
 // use super::CronJob;
 
@@ -24,50 +16,37 @@ pub struct RealTakeoffLookup {}
 
 impl RealTakeoffLookup {
 
-    fn list_takeoffs(ts: i64, mysql: &mut MySQL) -> Vec<LogbookItem> {
-        let sql = format!("SELECT id, address, address_type, ts, location_icao FROM logbook_events WHERE ts >= {} AND event='T';", ts - RTL_RUN_INTERVAL as i64);    // RUN_INTERVAL = 60
-
-        let mut conn = mysql.get_connection();
+    fn list_takeoffs(ts: i64, repository: &mut Box<dyn LogbookRepository>) -> Vec<LogbookItem> {
+        let takeoffs = repository.list_takeoffs_since(ts - RTL_RUN_INTERVAL as i64)    // RUN_INTERVAL = 60
+            .unwrap_or_else(|e| { error!("{e}"); vec![] });
 
-        let entries: Vec<LogbookItem> = conn.query_map(sql, 
-            |mut row: Row| {
-                let id = row.take("id").unwrap();
-                let addr = row.take("address").unwrap();
-                let addr_type = AddressType::from_short_str(row.take("address_type").unwrap());
-                let ts = row.take("ts").unwrap();
-
-                let mut takeoff_icao = "".to_string();  // jebacka s prevodem NULL v db na neco pouzitelneho tady
-                let val = row.take_opt("location_icao");
-                if let Some(val) = val {
-                    match val {
-                        Ok(val) => takeoff_icao = val,
-                        Err(_) => {takeoff_icao = "".to_string()}
-                    }
-                }
+        takeoffs.into_iter().map(|event| {
+            let mut item = LogbookItem::new(event.id, event.address, event.address_type, event.ts, event.location_icao);
+            item.aircraft_type = event.aircraft_type;
 
-                LogbookItem::new(id, addr, addr_type, ts, takeoff_icao)
-            }
-        ).unwrap();
-        
-        entries
+            item
+        }).collect()
     }
 
-    pub fn check_takeoffs() {
-        let mysql_pool = MySQL::new();
-        if mysql_pool.is_err() {
-            warn!("Could not obtain MySQL connection, skipping check_takeoffs().");
-            return;
-        }
-        let mut mysql = mysql_pool.unwrap();
-
+    pub fn check_takeoffs(clock: &dyn Clock) {
+        let mut repository = match logbook_repository::get_logbook_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                warn!("Could not obtain db connection, skipping check_takeoffs(): {e}");
+                return;
+            }
+        };
 
-        let airfield_manager = AirfieldManager::new(AIRFIELDS_FILEPATH);    // (50ms) !!ultra inefficient to read & parse the file every time!!
-        let influx_db_name = get_influx_db_name();
+        let airfield_manager = airfield_service::airfields();
+        let detection_profiles = detection_profiles::profiles();
 
-        let ts = Utc::now().timestamp();
-        let mut takeoffs = RealTakeoffLookup::list_takeoffs(ts, &mut mysql);
+        let ts = clock.now();
+        let mut takeoffs = RealTakeoffLookup::list_takeoffs(ts, &mut repository);
 
-        let influx_db_client = Client::new(Url::parse(&get_influx_url()).unwrap(), Some(("", ""))).unwrap();
+        let mut track_store = match track_store::get_track_store() {
+            Some(track_store) => track_store,
+            None => return,
+        };
 
         let mut num_modified_takeoffs = 0_u64;
         for logbook_item in takeoffs.iter_mut() {
@@ -75,46 +54,32 @@ impl RealTakeoffLookup {
             let window_end_ts = logbook_item.takeoff_ts - 2;    // [s]
             let window_start_ts = window_end_ts - 59;           // [s]
 
-            // get flight data from influx:
-            let q = format!("SELECT lat, lon, gs FROM {influx_db_name}..{INFLUX_SERIES_NAME} WHERE addr='{addr}' AND time >= {window_start_ts}000000000 AND time <= {window_end_ts}000000000 ORDER BY time DESC");
-            let query = Query::new(q);
-            let res: Result<DataFrame, ClientError> = influx_db_client.fetch_dataframe(query);
-
-            if res.is_err() {
-                // warn!("RTL: no influx data for '{addr}' between {window_start_ts} and {window_end_ts}.");
-                continue;
-            }
-
-            let df = res.unwrap();
+            // positions before the take-off, the latest one first:
+            let track: Vec<TrackPoint> = track_store.get_track(&addr, window_start_ts, window_end_ts).into_iter().rev().collect();
 
-            let index = df.index;
-
-            let cols = df.columns;
-            let latitudes: &Column = cols.get("lat").unwrap();
-            let longitudes = cols.get("lon").unwrap();
-            let ground_speeds = cols.get("gs").unwrap();
+            let takeoff_roll_gs = detection_profiles.get(&logbook_item.aircraft_type).takeoff_roll_gs;
 
             // find minimal ground speed index:
             let mut dirty = false;
             let mut min_gs = i64::MAX;
             let mut min_gs_index = 0;
-            for i in 0..index.len() {
-                let gs = ground_speeds.get_int_value(i).unwrap();
+            for (i, p) in track.iter().enumerate() {
+                let gs = p.gs;
                 if gs <= min_gs {
                     min_gs = gs;
                     min_gs_index = i;
                     dirty = true;
 
-                    if gs <= 40_i64 {   // TODO getGroundSpeedThreshold(logbookItem.aircraft_type, forEvent='T'):
+                    if gs as f64 <= takeoff_roll_gs {
                         break;
                     } 
                 }
             }
 
             if dirty {
-                logbook_item.takeoff_ts = index[min_gs_index].timestamp();
-                logbook_item.takeoff_lat = latitudes.get_float_value(min_gs_index).unwrap_or(0_f64);
-                logbook_item.takeoff_lon = longitudes.get_float_value(min_gs_index).unwrap_or(0_f64);
+                logbook_item.takeoff_ts = track[min_gs_index].ts;
+                logbook_item.takeoff_lat = track[min_gs_index].lat;
+                logbook_item.takeoff_lon = track[min_gs_index].lon;
 
                 if logbook_item.takeoff_icao == "" {
                     let takeoff_location = airfield_manager.get_nearest(logbook_item.takeoff_lat, logbook_item.takeoff_lon);
@@ -123,16 +88,9 @@ impl RealTakeoffLookup {
                     }
                 } 
 
-                let location_icao_sql = if logbook_item.takeoff_icao != "" { format!("'{}'", logbook_item.takeoff_icao) } else { "null".into() };
-
-                let update_sql = format!("UPDATE logbook_events SET ts={}, lat={:.5}, lon={:.5}, location_icao={location_icao_sql} WHERE id={};", 
-                    logbook_item.takeoff_ts, logbook_item.takeoff_lat, logbook_item.takeoff_lon, logbook_item.id);
-
-                let mut conn = mysql.get_connection();
-                match conn.query_drop(&update_sql) {
-                    Ok(_) => (),
-                    Err(e) => error!("Error when executing query '{update_sql}': {e}")
-                };
+                if let Err(e) = repository.update_takeoff(logbook_item.id, logbook_item.takeoff_ts, logbook_item.takeoff_lat, logbook_item.takeoff_lon, &logbook_item.takeoff_icao) {
+                    error!("{e}");
+                }
 
                 num_modified_takeoffs += 1;
             }
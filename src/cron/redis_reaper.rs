@@ -1,22 +1,24 @@
 
 use std::vec;
-use log::warn;
+use log::{error, warn};
 
-use chrono::Utc;
 use log::info;
-use mysql::prelude::Queryable;
-use mysql::Row;
-use rinfluxdb::influxql::Query;
-use rinfluxdb_influxql::ClientError;
-use ogn_client::data_structures::{AddressType, AircraftType};
-
-use crate::airfield_manager::AirfieldManager;
-use crate::configuration::{AIRFIELDS_FILEPATH, REDIS_RECORD_EXPIRATION, get_influx_db_name};
-use crate::db::dataframe::DataFrame;
-use crate::db::mysql::MySQL;
-use crate::db::influxdb;
-use crate::db::redis;
+use ogn_client::data_structures::AddressType;
+
+use crate::airfield_manager::airfield_service;
+use crate::alerts::{LostContactAlert, OutlandingAlert};
+use crate::clock::Clock;
+use crate::configuration::{REDIS_RECORD_EXPIRATION, debug, get_mqtt_config};
+use crate::worker::data_structures::{AircraftStatus, AircraftStatusWithTs};
+use crate::db::entry_builder;
+use crate::db::logbook_repository::{self, LogbookRepository};
+use crate::db::track_store::{self, TrackPoint};
+use crate::db::state_store::{self, StateStore};
 use crate::db::data_structures::LogbookEvent;
+use crate::detection_profiles::{self, DetectionMode};
+use crate::mqtt::{Mqtt, MqttMessage};
+use crate::worker::flight_phase_detector::{FlightPhaseDetector, FlightState};
+use crate::worker::terrain;
 
 
 pub struct RedisReaper {}
@@ -26,152 +28,231 @@ pub const RR_RUN_INTERVAL: u64 = 5*60;  // [s]
 pub const RR_STALE_INTERVAL_2: i64 = 2 * 60 * 60;  // [s]
 // pub const RR_TTL_LIMIT: i64 = REDIS_RECORD_EXPIRATION as i64 - _RR_STALE_INTERVAL_1;
 pub const RR_GS_THRESHOLD: i64 = 20;    // [km/h] glider
+pub const RR_ALERT_STALE_INTERVAL: i64 = 10 * 60;   // [s] no beacons from an airborne aircraft for this long
+pub const RR_ALERT_AGL_LIMIT: i64 = 300;    // [m]
+pub const RR_ALERT_VS_LIMIT: f64 = -5.0;    // [m/s]
+pub const RR_ALERT_NUM_POSITIONS: usize = 20;
 
 impl RedisReaper {
 
-    fn find_most_recent_takeoff(mysql: &mut MySQL, address: &str, address_type: AddressType) -> Option<LogbookEvent> {
-        let addr_type = address_type.as_short_str();
-
-        let sql = format!("SELECT id, ts, address, address_type, aircraft_type, lat, lon, location_icao 
-            FROM logbook_events 
-            WHERE address = '{address}' AND address_type='{addr_type}' AND event='T' 
-            ORDER by ts DESC LIMIT 1;");
-
-        let entries: Vec<LogbookEvent> = mysql.get_connection().query_map(sql, 
-            |mut row: Row| {
+    /// Decides whether the last known position of an airborne aircraft which stopped transmitting deserves an alert.
+    /// @return reason of the alert
+    fn lost_contact_reason(last_position: &TrackPoint, near_airfield: bool) -> Option<String> {
+        if !near_airfield {
+            Some("away from any airfield".into())
+        } else if last_position.agl > 0 && last_position.agl < RR_ALERT_AGL_LIMIT {
+            Some(format!("low AGL {} m", last_position.agl))
+        } else if last_position.vs < RR_ALERT_VS_LIMIT {
+            Some(format!("descending at {:.1} m/s", last_position.vs))
+        } else {
+            None
+        }
+    }
 
-                let mut takeoff_icao = "".to_string();  // jebacka s prevodem NULL v db na neco pouzitelneho tady
-                let val = row.take_opt("location_icao");
-                if let Some(val) = val {
-                    match val {
-                        Ok(val) => takeoff_icao = val,
-                        Err(_) => {takeoff_icao = "".to_string()}
-                    }
-                }
+    /// The contact can get lost more than once a day - an alert is raised once per last heard position,
+    /// i.e. again after the aircraft transmitted in the meantime.
+    fn alerted_already(state_store: &mut Box<dyn StateStore>, key_prefix: &str, last_position: &TrackPoint) -> bool {
+        state_store.get_alert_ts(key_prefix) == Some(last_position.ts)
+    }
 
-                LogbookEvent {
-                    id: row.take("id").unwrap(),
-                    ts: row.take("ts").unwrap(),
-                    event: "T".into(),
-                    address: row.take("address").unwrap(),
-                    address_type: AddressType::from_short_str(row.take("address_type").unwrap()),
-                    aircraft_type: AircraftType::from(row.take("aircraft_type").unwrap()),
-                    lat: row.take("lat").unwrap(),
-                    lon: row.take("lon").unwrap(),
-                    location_icao: takeoff_icao,
-                }
+    /// Confirms a landing the worker deferred as a possible touch-and-go when no beacon followed the touchdown.
+    /// @return true if the aircraft has been landed
+    fn confirm_deferred_landing(state_store: &mut Box<dyn StateStore>, repository: &mut Box<dyn LogbookRepository>, detector: &FlightPhaseDetector,
+        key_prefix: &str, addr: &str, addr_type: &AddressType, now: i64) -> bool {
+        let prior = match (state_store.get_status(key_prefix), state_store.get_approach(key_prefix)) {
+            (Some(status), Some(approach)) if approach.touched => FlightState::new(status, 0_f64, Some(approach)),
+            _ => return false,
+        };
+
+        let takeoff_event = match repository.find_latest_takeoff(addr, addr_type) {
+            Ok(Some(takeoff_event)) => takeoff_event,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("{e}");
+                return false;
             }
-        ).unwrap();
+        };
+
+        let detection = detector.confirm_deferred_landing(&prior, &takeoff_event.aircraft_type, now);
+        match &detection.state {
+            Some(state) if state.status.is(AircraftStatus::OnGround) => {
+                state_store.set_status(key_prefix, &state.status, REDIS_RECORD_EXPIRATION);
+                state_store.del_approach(key_prefix);
+            },
+            Some(_) => return false,    // still within the touch-and-go window
+            None => state_store.forget(key_prefix),
+        }
 
-        
-        for entry in entries {
-            return Some(entry);
+        for event in detection.events.iter() {
+            let landing = LogbookEvent {
+                id: 0,
+                ts: event.ts,
+                event: event.event.to_string(),
+                address: addr.into(),
+                address_type: takeoff_event.address_type.clone(),
+                aircraft_type: takeoff_event.aircraft_type.clone(),
+                lat: event.lat,
+                lon: event.lon,
+                location_icao: event.icao_location.clone().unwrap_or_default(),
+                flight_time: event.flight_time,
+                outlanding: false,  // touched down at an airfield
+                elevation: None,
+            };
+            if let Err(e) = entry_builder::store_event(repository, &landing) {
+                error!("{e}");
+            }
         }
 
-        None
+        true
     }
 
-    pub fn do_work() {
-        let mysql_pool = MySQL::new();
-        if mysql_pool.is_err() {
-            warn!("Could not obtain MySQL connection, skipping do_work().");
-            return;
+    /// An airborne aircraft is considered landed when it is slow & low or when we haven't heard from it for too long.
+    fn landing_suspected(last_position: &TrackPoint, now: i64) -> bool {
+        let agl = last_position.agl;
+        if agl > 0 && agl < 100 && last_position.gs < RR_GS_THRESHOLD {
+            true
+        } else {
+            now - last_position.ts > RR_STALE_INTERVAL_2
         }
-        let mut mysql = mysql_pool.unwrap();
+    }
 
-        let mut redis = redis::get_client();
-        let influx_db_client = influxdb::get_client();
-        let airfield_manager = AirfieldManager::new(AIRFIELDS_FILEPATH);
-        let influx_db_name = get_influx_db_name();
+    pub fn do_work(clock: &dyn Clock) {
+        let mut repository = match logbook_repository::get_logbook_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                warn!("Could not obtain db connection, skipping do_work(): {e}");
+                return;
+            }
+        };
+
+        let mut state_store = state_store::get_state_store();
+        let mut track_store = match track_store::get_track_store() {
+            Some(track_store) => track_store,
+            None => return,
+        };
+        let airfield_manager = airfield_service::airfields();
+        let detection_profiles = detection_profiles::profiles();
+        let detector = FlightPhaseDetector::new(detection_profiles.clone());
 
         // list all airborne airplanes:
-        let res = redis.keys("*status");
-        let status_keys = match res {
-            Ok(keys) => keys,
-            Err(_) => vec![],
-        };  // key in form address-status
-
-        let mut airborne:Vec<String> = vec![];
-        for status_key in status_keys {
-            let rec: String = match redis.get(&status_key) {    
-                Ok(str) => str,
-                Err(_) => "".into(),
-            };  // rec in form "status;ts" where status 1 == airborne
-
-            if !rec.starts_with("1") { continue; }
-
-            let addr = status_key.split("-").collect::<Vec<&str>>()[0]; // in fact addressTypeStr + addr (e.g. I123456, F123456, O123456, ..)
-            airborne.push(addr.into());
-        }
+        let airborne: Vec<String> = state_store.list_statuses().into_iter()
+            .filter(|(_, status)| status.is(AircraftStatus::Airborne))
+            .map(|(key_prefix, _)| key_prefix)  // in fact addressTypeStr + addr (e.g. I123456, F123456, O123456, ..)
+            .collect();
 
         let mut num_landed = 0;
+        let mut outlanding_alerts: Vec<MqttMessage> = vec![];
+        let mut lost_contact_alerts: Vec<MqttMessage> = vec![];
 
         for addr in airborne {
             let prefix = &addr[..1];
             let addr = &addr[1..];
             let addr_type = AddressType::from_short_str(prefix.into());
             let addr_prefix_long = addr_type.as_long_str();
+            let key_prefix = format!("{prefix}{addr}");
+
+            // a touchdown near an airfield not followed by any beacon (the tracker got switched off after landing):
+            if RedisReaper::confirm_deferred_landing(&mut state_store, &mut repository, &detector, &key_prefix, addr, &addr_type, clock.now()) {
+                num_landed += 1;
+                continue;
+            }
 
-            // get last received beacon:
-            let q = format!("SELECT agl, gs, lat, lon FROM {influx_db_name}..pos WHERE addr='{addr_prefix_long}{addr}' ORDER BY time DESC LIMIT 1;");
-            let query = Query::new(&q);
-            let res: Result<DataFrame, ClientError> = influx_db_client.fetch_dataframe(query);
-            if res.is_err() {
+            // get last received beacons:
+            let positions = track_store.get_last_positions(&format!("{addr_prefix_long}{addr}"), RR_ALERT_NUM_POSITIONS);
+            if positions.len() == 0 {
                 // warn!("RR: no last position in influx for '{addr}'.");
                 continue;
             }
 
-            let df = res.unwrap();
-            // println!("DF:{}", df);
-            let cols = df.columns;
-            let ts = df.index[0].timestamp();    // utc ts
-            let agl = cols.get("agl").unwrap().get_int_value(0).unwrap_or(0);
-            let gs = cols.get("gs").unwrap().get_int_value(0).unwrap_or(0);
-            let lat = cols.get("lat").unwrap().get_float_value(0).unwrap_or(0_f64);
-            let lon = cols.get("lon").unwrap().get_float_value(0).unwrap_or(0_f64);
-
-            let mut landing_suspected = false;
-            if agl > 0 && agl < 100 && gs < RR_GS_THRESHOLD {
-                landing_suspected = true;
-            } else {
-                let last_position_age = Utc::now().timestamp() - ts;
-                if last_position_age > RR_STALE_INTERVAL_2 {
-                    landing_suspected = true;
+            let last_position = &positions[0];
+            let ts = last_position.ts;    // utc ts
+            let lat = last_position.lat;
+            let lon = last_position.lon;
+
+            // raise an alert if the contact got lost in a suspicious situation (once per loss of contact):
+            let last_position_age = clock.now() - ts;
+            if last_position_age > RR_ALERT_STALE_INTERVAL && !RedisReaper::alerted_already(&mut state_store, &key_prefix, last_position) {
+                let reason = RedisReaper::lost_contact_reason(last_position, airfield_manager.get_nearest(lat, lon).is_some());
+                if let Some(reason) = reason {
+                    warn!("RR: lost contact with {prefix}{addr} {last_position_age}s ago ({reason}) at {lat:.5} {lon:.5}");
+
+                    let alert = LostContactAlert {
+                        address: addr.into(),
+                        address_type: addr_type.clone(),
+                        reason,
+                        positions: positions.clone(),
+                    };
+                    alert.send_to_webhook();
+                    lost_contact_alerts.push(alert.as_mqtt_message());
+
+                    state_store.set_alert_ts(&key_prefix, ts, REDIS_RECORD_EXPIRATION);
                 }
             }
 
-            if landing_suspected {
-                // set status as onGround (0) in redis (or delete?):
-                let key = format!("{prefix}{addr}-status");
-                redis.set(&key, "0;0").unwrap();   // 0 = on-ground; ts=0 to indicate forced landing
-                redis.expire(&key, REDIS_RECORD_EXPIRATION).unwrap();
+            if RedisReaper::landing_suspected(last_position, clock.now()) {
+                // set status as onGround in the state store (or delete?):
+                let status = AircraftStatusWithTs::new(AircraftStatus::OnGround, 0);    // ts=0 to indicate forced landing
+                state_store.set_status(&key_prefix, &status, REDIS_RECORD_EXPIRATION);
+                state_store.del_approach(&key_prefix);  // a touch-and-go candidate which never got confirmed
 
 
                 // look-up related takeoff record:
-                let takeoff_event = RedisReaper::find_most_recent_takeoff(&mut mysql, addr, addr_type);
-                if takeoff_event.is_some() {    // create a LANDING logbook_event -> a stored procedure then creates a logbook_entry (flight)
+                let takeoff_event = repository.find_latest_takeoff(addr, &addr_type).unwrap_or_else(|e| { warn!("{e}"); None });
+                if takeoff_event.is_some() {    // create a LANDING logbook_event and close the logbook_entry (flight)
                     let takeoff_event = takeoff_event.unwrap();
                     // println!("TE: {:?}", takeoff_event);
 
                     let mut flight_time = ts - takeoff_event.ts;
                     if flight_time < 0 { flight_time = 0; };
 
-                    let mut icao_location = takeoff_event.location_icao;
-                    if icao_location == "" {
-                        icao_location = match airfield_manager.get_nearest(lat, lon) {
-                            Some(loc) => loc,
-                            None => "".into(),
-                        };
+                    // the landing site is where the aircraft was heard last, not where it took off:
+                    let mode = detection_profiles.get(&takeoff_event.aircraft_type).mode;
+                    let sites = match mode {
+                        DetectionMode::FootLaunch => airfield_service::launch_sites(),
+                        DetectionMode::Hover => airfield_service::heliports(),
+                        DetectionMode::Speed => None,
+                    };
+                    let icao_location = sites.as_ref().and_then(|sites| sites.get_nearest(lat, lon))
+                        .or_else(|| airfield_manager.get_nearest(lat, lon))
+                        .unwrap_or_default();
+
+                    // a landing away from any known airfield:
+                    let outlanding = icao_location == "";
+                    let mut elevation = None;
+                    if outlanding {
+                        elevation = terrain::shared().get_value(lat, lon);
                     }
 
-                    let location_icao_sql = if icao_location != "" { format!("'{icao_location}'") } else { "null".into() };
-                    
-                    let sql = format!("INSERT INTO logbook_events (ts, address, address_type, aircraft_type, event, lat, lon, location_icao, flight_time)
-                        VALUES 
-                        ({ts}, '{addr}', '{}', {}, 'L', {lat:.5}, {lon:.5}, {location_icao_sql}, {flight_time});",
-                        takeoff_event.address_type.as_short_str(), takeoff_event.aircraft_type.value());
+                    if outlanding && mode == DetectionMode::Speed {  // landing in a field is the daily routine of foot-launched aircraft and helicopters
+                        let alert = OutlandingAlert {
+                            ts,
+                            address: addr.into(),
+                            address_type: takeoff_event.address_type.clone(),
+                            aircraft_type: takeoff_event.aircraft_type.clone(),
+                            lat,
+                            lon,
+                            elevation,
+                        };
+                        outlanding_alerts.push(alert.as_mqtt_message());
+                    }
 
-                    mysql.get_connection().exec_drop(sql, ()).unwrap();
+                    let landing = LogbookEvent {
+                        id: 0,
+                        ts,
+                        event: "L".into(),
+                        address: addr.into(),
+                        address_type: takeoff_event.address_type.clone(),
+                        aircraft_type: takeoff_event.aircraft_type.clone(),
+                        lat,
+                        lon,
+                        location_icao: icao_location,
+                        flight_time,
+                        outlanding,
+                        elevation,
+                    };
+                    if let Err(e) = entry_builder::store_event(&mut repository, &landing) {
+                        error!("{e}");
+                    }
 
                     // TODO..
                     // addrTypeNum = REVERSE_ADDRESS_TYPE.get(addrType, 1)
@@ -187,6 +268,89 @@ impl RedisReaper {
         if num_landed > 0 {
             info!("RedisReaper: cleared {num_landed} stale records");
         }
+
+        let messages: Vec<MqttMessage> = lost_contact_alerts.into_iter().chain(outlanding_alerts.into_iter()).collect();
+        if messages.len() > 0 && !debug() {
+            let (mqtt_id, mqtt_host, mqtt_port, mqtt_username, mqtt_password) = get_mqtt_config();
+            let mut mqtt = Mqtt::new(&format!("{mqtt_id}-rr"), &mqtt_host, mqtt_port, &mqtt_username, &mqtt_password);
+            mqtt.send_mqtt_messages(&messages);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use ogn_client::data_structures::{AircraftBeacon, AddressType};
+
+    use std::sync::Arc;
+
+    use crate::clock::{Clock, SimulatedClock};
+    use crate::db::logbook_repository::LogbookRepository;
+    use crate::db::logbook_repository::sqlite_logbook_repository::SqliteLogbookRepository;
+    use crate::db::state_store::StateStore;
+    use crate::db::state_store::memory_state_store::MemoryStateStore;
+    use crate::db::track_store::{TrackPoint, TrackStore};
+    use crate::db::track_store::sqlite_track_store::SqliteTrackStore;
+    use crate::worker::position_worker::beacon_into_position;
+
+    use super::{RedisReaper, RR_STALE_INTERVAL_2};
+
+    fn position(ts: i64, agl: i64, gs: i64) -> TrackPoint {
+        TrackPoint { ts, lat: 49.0, lon: 16.0, alt: 500 + agl, agl, gs, vs: 0.0 }
+    }
+
+    #[test]
+    fn stale_records_are_reaped() {
+        let clock = SimulatedClock::new(1_700_000_000);
+        let thermalling = position(clock.now(), 800, 90);
+        assert!(!RedisReaper::landing_suspected(&thermalling, clock.now()));
+
+        clock.advance_to(thermalling.ts + RR_STALE_INTERVAL_2);
+        assert!(!RedisReaper::landing_suspected(&thermalling, clock.now()));
+
+        clock.advance_to(thermalling.ts + RR_STALE_INTERVAL_2 + 1);
+        assert!(RedisReaper::landing_suspected(&thermalling, clock.now()));
+    }
+
+    #[test]
+    fn slow_and_low_is_landed() {
+        let clock = SimulatedClock::new(1_700_000_000);
+        assert!(RedisReaper::landing_suspected(&position(clock.now(), 20, 5), clock.now()));
+        assert!(!RedisReaper::landing_suspected(&position(clock.now(), 20, 60), clock.now()));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn slow_and_low_from_stored_position() {
+        let filepath = std::env::temp_dir().join(format!("ogn-logbook-reaper-{}.sqlite", std::process::id()));
+        let filepath = filepath.to_str().unwrap();
+        SqliteLogbookRepository::new(filepath).unwrap().migrate().unwrap();
+        let mut store = SqliteTrackStore::new(filepath).unwrap();
+
+        let beacon = AircraftBeacon { ts: 1_700_000_000, addr: "123456".into(), addr_type: AddressType::Ogn, lat: 49.0, lon: 16.0, altitude: 320, speed: 5, ..Default::default() };
+        store.insert_position(&beacon_into_position(&beacon, Some(20))).unwrap();
+
+        let last_position = &store.get_last_positions("OGN123456", 1)[0];
+        assert_eq!(last_position.agl, 20);
+        assert!(RedisReaper::landing_suspected(last_position, beacon.ts as i64));
+        assert_eq!(RedisReaper::lost_contact_reason(last_position, true), Some("low AGL 20 m".into()));
+
+        drop(store);
+        let _ = std::fs::remove_file(filepath);
+    }
+
+    #[test]
+    fn alert_again_after_the_contact_got_lost_again() {
+        let clock = Arc::new(SimulatedClock::new(1_700_000_000));
+        let mut state_store: Box<dyn StateStore> = Box::new(MemoryStateStore::new(None, clock.clone()));
+
+        let first_loss = position(clock.now(), 150, 90);
+        assert!(!RedisReaper::alerted_already(&mut state_store, "O123456", &first_loss));
+        state_store.set_alert_ts("O123456", first_loss.ts, 3600);
+        assert!(RedisReaper::alerted_already(&mut state_store, "O123456", &first_loss));
+
+        // heard again and then lost once more the same day:
+        let second_loss = position(clock.now() + 1800, 150, 90);
+        assert!(!RedisReaper::alerted_already(&mut state_store, "O123456", &second_loss));
+    }
+}
@@ -0,0 +1,145 @@
+use log::{info, warn, error};
+
+use crate::airfield_manager::{airfield_service, AirfieldManager};
+use crate::clock::Clock;
+use crate::cron::real_takeoff_lookup::RTL_RUN_INTERVAL;
+use crate::db::track_store::{self, TrackPoint};
+use crate::db::data_structures::LogbookEvent;
+use crate::db::logbook_repository::{self, LogbookRepository};
+
+pub const RWL_RUN_INTERVAL: u64 = 60;   // [s]
+const RWL_WINDOW: i64 = 60;             // [s] portion of the track next to the event searched for the ground roll
+const RWL_MIN_ROLL_DISTANCE: f64 = 0.3; // [km] shorter rolls do not give a reliable direction
+const RWL_MAX_AGE: i64 = 60 * 60;       // [s] older events are not looked at anymore
+
+pub struct RunwayLookup {}
+
+impl RunwayLookup {
+
+    /// Lists take-offs and landings at known airfields without runway.
+    /// The take-offs need to be amended by the RealTakeoffLookup first (ts & position of the take-off roll start).
+    fn list_events(ts: i64, repository: &mut Box<dyn LogbookRepository>) -> Vec<LogbookEvent> {
+        repository.list_events_without_runway(ts - RWL_MAX_AGE, ts - 2 * RTL_RUN_INTERVAL as i64, 100)
+            .unwrap_or_else(|e| { error!("{e}"); vec![] })
+    }
+
+    fn distance(a: &TrackPoint, b: &TrackPoint) -> f64 {
+        AirfieldManager::get_distance_in_km(a.lat.to_radians(), a.lon.to_radians(), b.lat.to_radians(), b.lon.to_radians())
+    }
+
+    fn bearing(a: &TrackPoint, b: &TrackPoint) -> f64 {
+        AirfieldManager::get_bearing(a.lat.to_radians(), a.lon.to_radians(), b.lat.to_radians(), b.lon.to_radians())
+    }
+
+    /// Direction of the take-off roll - from its start to the first position far enough.
+    /// @param track: positions since the roll start ordered by time
+    /// @return (bearing [deg], roll start)
+    fn takeoff_direction(track: &Vec<TrackPoint>) -> Option<(f64, &TrackPoint)> {
+        let start = track.first()?;
+        let end = track.iter().find(|p| RunwayLookup::distance(start, p) >= RWL_MIN_ROLL_DISTANCE)?;
+
+        Some((RunwayLookup::bearing(start, end), start))
+    }
+
+    /// Direction of the landing - from the last position far enough to the landing position.
+    /// @param track: positions before (and at) the landing ordered by time
+    /// @return (bearing [deg], landing position)
+    fn landing_direction(track: &Vec<TrackPoint>) -> Option<(f64, &TrackPoint)> {
+        let end = track.last()?;
+        let start = track.iter().rev().find(|p| RunwayLookup::distance(p, end) >= RWL_MIN_ROLL_DISTANCE)?;
+
+        Some((RunwayLookup::bearing(start, end), end))
+    }
+
+    pub fn find_runways(clock: &dyn Clock) {
+        let mut repository = match logbook_repository::get_logbook_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                warn!("Could not obtain db connection, skipping find_runways(): {e}");
+                return;
+            }
+        };
+
+        let events = RunwayLookup::list_events(clock.now(), &mut repository);
+        if events.len() == 0 {
+            return;
+        }
+
+        let airfield_manager = airfield_service::airfields();
+        let mut track_store = match track_store::get_track_store() {
+            Some(track_store) => track_store,
+            None => return,
+        };
+
+        let mut num_found = 0;
+        for e in events.iter() {
+            let addr = format!("{}{}", e.address_type.as_long_str(), e.address);
+
+            let direction = if e.event == "T" {
+                let track = track_store.get_track(&addr, e.ts, e.ts + RWL_WINDOW);
+                RunwayLookup::takeoff_direction(&track).map(|(bearing, p)| (bearing, p.lat, p.lon))
+            } else {
+                let track = track_store.get_track(&addr, e.ts - RWL_WINDOW, e.ts);
+                RunwayLookup::landing_direction(&track).map(|(bearing, p)| (bearing, p.lat, p.lon))
+            };
+
+            let runway = direction.and_then(|(bearing, lat, lon)| airfield_manager.get_runway(&e.location_icao, bearing, lat, lon));
+
+            if runway.is_some() {
+                num_found += 1;
+            }
+
+            // '' = looked up but not determined
+            if let Err(err) = repository.set_runway(e.id, &runway.unwrap_or_default()) {
+                error!("{err}");
+            }
+        }
+
+        if num_found > 0 {
+            info!("Num runways found: {num_found}/{}", events.len());
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::track_store::TrackPoint;
+
+    use super::RunwayLookup;
+
+    /// A position every 4 s moving by (dlat, dlon) [deg] each time.
+    fn roll(n: usize, dlat: f64, dlon: f64) -> Vec<TrackPoint> {
+        (0..n).map(|i| TrackPoint { ts: 1000 + 4 * i as i64, lat: 49.0 + dlat * i as f64, lon: 16.0 + dlon * i as f64, alt: 300, agl: 0, gs: 60, vs: 0.0 }).collect()
+    }
+
+    #[test]
+    fn takeoff_roll_to_the_north() {
+        let track = roll(10, 0.0005, 0.0);
+        let (bearing, start) = RunwayLookup::takeoff_direction(&track).unwrap();
+        assert!(bearing < 0.1 || bearing > 359.9);
+        assert_eq!(start.ts, 1000);
+
+        // rolled less than RWL_MIN_ROLL_DISTANCE:
+        assert!(RunwayLookup::takeoff_direction(&roll(5, 0.0005, 0.0)).is_none());
+        assert!(RunwayLookup::takeoff_direction(&vec![]).is_none());
+    }
+
+    #[test]
+    fn landing_roll_to_the_east() {
+        let mut track = roll(10, 0.0, 0.0008);
+        // stopped at the end of the roll:
+        let last = track.last().unwrap().clone();
+        track.push(TrackPoint { ts: last.ts + 4, ..last });
+
+        let (bearing, landing) = RunwayLookup::landing_direction(&track).unwrap();
+        assert!((bearing - 90.0).abs() < 0.5);
+        assert_eq!(landing.ts, 1040);
+
+        // westwards:
+        let (bearing, _) = RunwayLookup::landing_direction(&roll(10, 0.0, -0.0008)).unwrap();
+        assert!((bearing - 270.0).abs() < 0.5);
+
+        assert!(RunwayLookup::landing_direction(&roll(3, 0.0, 0.0008)).is_none());
+    }
+}
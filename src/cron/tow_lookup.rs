@@ -0,0 +1,288 @@
+use log::{info, warn, error};
+
+use ogn_client::data_structures::AircraftType;
+
+use crate::airfield_manager::AirfieldManager;
+use crate::clock::Clock;
+use crate::db::data_structures::LogbookItem;
+use crate::db::track_store::{self, TrackPoint, TrackStore};
+use crate::db::logbook_repository::{self, LogbookRepository, TowRelease};
+use crate::worker::terrain;
+
+pub const TL_RUN_INTERVAL: u64 = 60;    // [s]
+const TL_MAX_TAKEOFF_TS_DIFF: i64 = 10; // [s] max difference of glider and tow-plane take-off times
+const TL_CHECK_WINDOW: i64 = 60;        // [s] portion of the climb after take-off where the tracks are compared
+const TL_MAX_TS_DIFF: i64 = 2;          // [s] max time difference of two positions to be compared
+const TL_MIN_COMMON_POINTS: usize = 5;
+const TL_MAX_DISTANCE: f64 = 0.3;       // [km] max average glider-to-tow distance during the climb
+const TL_RELEASE_WINDOW: i64 = 20 * 60; // [s] max expected tow duration
+const TL_RELEASE_DISTANCE: f64 = 0.2;   // [km] glider-to-tow distance considered as separated
+const TL_RELEASE_MIN_POINTS: usize = 3; // num of consecutive separated positions to confirm the release
+const TL_LOOKBACK: i64 = 24 * 3600;     // [s] entries which could not be checked for longer are not retried anymore
+
+static GLIDERS: [AircraftType; 1] = [AircraftType::Glider];
+static TOW_PLANES: [AircraftType; 2] = [AircraftType::TowPlane, AircraftType::PoweredAircraft];
+
+pub struct TowLookup {}
+
+impl TowLookup {
+
+    /// Lists landed gliders and tow planes which have not been checked yet (including the force-landed ones
+    /// whose landing time is the last position heard, and the ones a failed run has not got to).
+    fn list_new_entries(ts: i64, repository: &mut Box<dyn LogbookRepository>) -> Vec<LogbookItem> {
+        repository.list_tow_unchecked_entries(ts - TL_LOOKBACK, &[&GLIDERS[..], &TOW_PLANES[..]].concat())
+            .unwrap_or_else(|e| { error!("{e}"); vec![] })
+    }
+
+    /// Lists not yet paired entries of the opposite role (tow planes for a glider and vice versa) which took off at about the same time from the same place.
+    fn list_counterparts(item: &LogbookItem, repository: &mut Box<dyn LogbookRepository>) -> Vec<LogbookItem> {
+        let counterpart_types: &[AircraftType] = if GLIDERS.contains(&item.aircraft_type) { &TOW_PLANES } else { &GLIDERS };
+
+        repository.list_tow_counterparts(item, counterpart_types, TL_MAX_TAKEOFF_TS_DIFF)
+            .unwrap_or_else(|e| { error!("{e}"); vec![] })
+    }
+
+    fn get_track(track_store: &mut Box<dyn TrackStore>, item: &LogbookItem, start_ts: i64, end_ts: i64) -> Vec<TrackPoint> {
+        let addr = format!("{}{}", item.addr_type.as_long_str(), item.addr);
+        track_store.get_track(&addr, start_ts, end_ts)
+    }
+
+    /// Pairs positions of two tracks which were recorded at (about) the same time.
+    /// @return vector of (glider position, distance between the aircraft in km)
+    fn align_tracks<'a>(glider_track: &'a Vec<TrackPoint>, tow_track: &Vec<TrackPoint>) -> Vec<(&'a TrackPoint, f64)> {
+        let mut aligned = Vec::new();
+
+        let mut j = 0;
+        for g in glider_track.iter() {
+            while j + 1 < tow_track.len() && (tow_track[j + 1].ts - g.ts).abs() <= (tow_track[j].ts - g.ts).abs() {
+                j += 1;
+            }
+            if j >= tow_track.len() { break; }
+
+            let t = &tow_track[j];
+            if (t.ts - g.ts).abs() > TL_MAX_TS_DIFF { continue; }
+
+            let dist = AirfieldManager::get_distance_in_km(g.lat.to_radians(), g.lon.to_radians(), t.lat.to_radians(), t.lon.to_radians());
+            aligned.push((g, dist));
+        }
+
+        aligned
+    }
+
+    /// Checks whether the two aircraft stayed close to each other during the climb.
+    fn tracks_stay_close(glider_track: &Vec<TrackPoint>, tow_track: &Vec<TrackPoint>) -> bool {
+        let aligned = TowLookup::align_tracks(glider_track, tow_track);
+        if aligned.len() < TL_MIN_COMMON_POINTS {
+            return false;
+        }
+
+        let avg_dist = aligned.iter().map(|(_, dist)| dist).sum::<f64>() / aligned.len() as f64;
+
+        avg_dist <= TL_MAX_DISTANCE
+    }
+
+    /// Finds the moment the glider and the tow plane positions diverged for good.
+    /// @return the last glider position before the separation
+    fn find_release_point<'a>(glider_track: &'a Vec<TrackPoint>, tow_track: &Vec<TrackPoint>) -> Option<&'a TrackPoint> {
+        let aligned = TowLookup::align_tracks(glider_track, tow_track);
+
+        let mut last_close: Option<&TrackPoint> = None;
+        let mut num_separated = 0;
+        for (g, dist) in aligned.into_iter() {
+            if dist <= TL_RELEASE_DISTANCE {
+                last_close = Some(g);
+                num_separated = 0;
+            } else {
+                num_separated += 1;
+                if num_separated >= TL_RELEASE_MIN_POINTS && last_close.is_some() {
+                    return last_close;
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn glider_tow_lookup(clock: &dyn Clock) {
+        let mut repository = match logbook_repository::get_logbook_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                warn!("Could not obtain db connection, skipping glider_tow_lookup(): {e}");
+                return;
+            }
+        };
+
+        let mut track_store = match track_store::get_track_store() {
+            Some(track_store) => track_store,
+            None => return,
+        };
+
+        let ts = clock.now();
+        let entries = TowLookup::list_new_entries(ts, &mut repository);
+        if entries.len() == 0 {
+            return;
+        }
+
+        let mut paired_ids: Vec<u64> = Vec::new();
+        let mut num_pairs = 0;
+        for item in entries.iter() {
+            if paired_ids.contains(&item.id) { continue; }   // paired already as a counterpart in this run
+
+            // without the track there is nothing to compare - try again in the next run:
+            if TowLookup::get_track(&mut track_store, item, item.takeoff_ts, item.takeoff_ts + TL_CHECK_WINDOW).is_empty() {
+                continue;
+            }
+
+            for counterpart in TowLookup::list_counterparts(item, &mut repository) {
+                if paired_ids.contains(&counterpart.id) { continue; }
+
+                let (glider, tow) = if GLIDERS.contains(&item.aircraft_type) { (item, &counterpart) } else { (&counterpart, item) };
+
+                let start_ts = glider.takeoff_ts.max(tow.takeoff_ts);
+                let glider_track = TowLookup::get_track(&mut track_store, glider, start_ts, start_ts + TL_CHECK_WINDOW);
+                let tow_track = TowLookup::get_track(&mut track_store, tow, start_ts, start_ts + TL_CHECK_WINDOW);
+
+                if TowLookup::tracks_stay_close(&glider_track, &tow_track) {
+                    if let Err(e) = repository.set_tow_ids(glider.id, tow.id) {
+                        error!("{e}");
+                        continue;
+                    }
+                    info!("TL: glider {} towed by {} from '{}'", glider.addr, tow.addr, glider.takeoff_icao);
+
+                    let glider_track = TowLookup::get_track(&mut track_store, glider, start_ts, start_ts + TL_RELEASE_WINDOW);
+                    let tow_track = TowLookup::get_track(&mut track_store, tow, start_ts, start_ts + TL_RELEASE_WINDOW);
+
+                    if let Some(p) = TowLookup::find_release_point(&glider_track, &tow_track) {
+                        let agl = terrain::shared().get_value(p.lat, p.lon).map(|elevation| (p.alt - elevation).max(0));
+                        let release = TowRelease { ts: p.ts, alt: p.alt, agl, duration: p.ts - glider.takeoff_ts };
+                        if let Err(e) = repository.set_tow_release(glider.id, tow.id, &release) {
+                            error!("{e}");
+                        }
+                        info!("TL: {} released at {} m AMSL after {} s", glider.addr, release.alt, release.duration);
+                    }
+
+                    paired_ids.push(glider.id);
+                    paired_ids.push(tow.id);
+                    num_pairs += 1;
+                    break;
+                }
+            }
+
+            if !paired_ids.contains(&item.id) {
+                if let Err(e) = repository.set_tow_checked(item.id) {
+                    error!("{e}");
+                }
+            }
+        }
+
+        if num_pairs > 0 {
+            info!("Num glider-tow pairs: {num_pairs}");
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::track_store::TrackPoint;
+
+    use super::TowLookup;
+
+    const CLOSE: f64 = 0.0005;      // [deg] of latitude ~ 56 m
+    const FAR: f64 = 0.005;         // [deg] of latitude ~ 556 m
+
+    /// Climb to the north with a position every dt seconds.
+    fn climb(n: usize, dt: i64) -> Vec<TrackPoint> {
+        (0..n).map(|i| TrackPoint { ts: 1000 + dt * i as i64, lat: 49.0 + 0.0004 * i as f64, lon: 16.0, alt: 300 + 10 * i as i64, agl: 10 * i as i64, gs: 110, vs: 2.5 }).collect()
+    }
+
+    /// The glider track shifted north by offset(i) [deg] and in time by ts_shift [s].
+    fn tow_track(glider_track: &Vec<TrackPoint>, ts_shift: i64, offset: impl Fn(usize) -> f64) -> Vec<TrackPoint> {
+        glider_track.iter().enumerate()
+            .map(|(i, p)| TrackPoint { ts: p.ts + ts_shift, lat: p.lat + offset(i), ..p.clone() })
+            .collect()
+    }
+
+    #[test]
+    fn close_climb() {
+        let glider = climb(15, 4);
+        let tow = tow_track(&glider, 0, |_| CLOSE);
+
+        let aligned = TowLookup::align_tracks(&glider, &tow);
+        assert_eq!(aligned.len(), 15);
+        assert_eq!(aligned[3].0.ts, 1012);
+        assert!((aligned[3].1 - 0.0556).abs() < 0.001);
+
+        assert!(TowLookup::tracks_stay_close(&glider, &tow));
+    }
+
+    #[test]
+    fn tracks_never_meet() {
+        let glider = climb(15, 4);
+        assert!(!TowLookup::tracks_stay_close(&glider, &tow_track(&glider, 0, |_| FAR)));
+        assert!(!TowLookup::tracks_stay_close(&glider, &vec![]));
+        assert!(TowLookup::align_tracks(&glider, &vec![]).is_empty());
+    }
+
+    #[test]
+    fn time_offsets() {
+        let glider = climb(15, 10);
+
+        // positions 1-2 s apart are still compared:
+        let tow = tow_track(&glider, 2, |_| CLOSE);
+        assert_eq!(TowLookup::align_tracks(&glider, &tow).len(), 15);
+        assert!(TowLookup::tracks_stay_close(&glider, &tow));
+
+        // beyond TL_MAX_TS_DIFF nothing matches:
+        let tow = tow_track(&glider, 3, |_| CLOSE);
+        assert!(TowLookup::align_tracks(&glider, &tow).is_empty());
+        assert!(!TowLookup::tracks_stay_close(&glider, &tow));
+    }
+
+    #[test]
+    fn tow_track_with_gaps() {
+        let glider = climb(15, 4);
+
+        // a tow position every 12 s - 5 common points are just enough:
+        let tow: Vec<TrackPoint> = tow_track(&glider, 0, |_| CLOSE).into_iter().step_by(3).collect();
+        assert_eq!(TowLookup::align_tracks(&glider, &tow).len(), 5);
+        assert!(TowLookup::tracks_stay_close(&glider, &tow));
+
+        // a tow position every 16 s - too few:
+        let tow: Vec<TrackPoint> = tow_track(&glider, 0, |_| CLOSE).into_iter().step_by(4).collect();
+        assert_eq!(TowLookup::align_tracks(&glider, &tow).len(), 4);
+        assert!(!TowLookup::tracks_stay_close(&glider, &tow));
+    }
+
+    #[test]
+    fn release_point() {
+        let glider = climb(60, 4);
+        let tow = tow_track(&glider, 0, |i| if i <= 30 { CLOSE } else { FAR });
+
+        let release = TowLookup::find_release_point(&glider, &tow).unwrap();
+        assert_eq!(release.ts, 1120);
+        assert_eq!(release.alt, 600);
+    }
+
+    #[test]
+    fn momentary_separation_is_not_a_release() {
+        let glider = climb(60, 4);
+
+        // shorter than TL_RELEASE_MIN_POINTS, the real release comes later:
+        let tow = tow_track(&glider, 0, |i| if i == 20 || i == 21 || i > 40 { FAR } else { CLOSE });
+        assert_eq!(TowLookup::find_release_point(&glider, &tow).unwrap().ts, 1160);
+
+        // the only separation is too short:
+        let tow = tow_track(&glider, 0, |i| if i == 20 || i == 21 { FAR } else { CLOSE });
+        assert!(TowLookup::find_release_point(&glider, &tow).is_none());
+    }
+
+    #[test]
+    fn tracks_never_separate() {
+        let glider = climb(60, 4);
+        assert!(TowLookup::find_release_point(&glider, &tow_track(&glider, 0, |_| CLOSE)).is_none());
+
+        // never together either:
+        assert!(TowLookup::find_release_point(&glider, &tow_track(&glider, 0, |_| FAR)).is_none());
+    }
+}
@@ -1,5 +1,10 @@
 pub mod dataframe;
 pub mod data_structures;
+pub mod entry_builder;
 pub mod influxdb;
+pub mod logbook_repository;
+pub mod migrations;
 pub mod mysql;
 pub mod redis;
+pub mod state_store;
+pub mod track_store;
@@ -19,7 +19,10 @@ pub struct LogbookEvent {
     pub aircraft_type: AircraftType,
     pub lat: f64,
     pub lon: f64,
-    pub location_icao: String,
+    pub location_icao: String,  // "" = not at any known airfield
+    pub flight_time: i64,       // [s] landings only
+    pub outlanding: bool,
+    pub elevation: Option<i64>, // [m] AMSL terrain elevation of outlandings
 }
 
 #[derive(Debug, Clone)]
@@ -46,6 +49,7 @@ pub struct LogbookItem {
     pub cn: String, 
     pub aircraft_type: AircraftType, 
     pub tow_id: i64,
+    pub tow_checked: bool,  // the TowLookup has looked for the counterpart already
 }
 
 impl LogbookItem {
@@ -73,6 +77,44 @@ impl LogbookItem {
             cn: "".into(), 
             aircraft_type: AircraftType::Unknown, 
             tow_id: 0_i64,
+            tow_checked: false,
          }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchMethod {
+    Unknown,
+    Winch,
+    Aerotow,
+    SelfLaunch,
+    Bungee,
+}
+
+impl LaunchMethod {
+    /// Single-letter code as stored in logbook_entries.launch_method
+    pub fn as_char(&self) -> char {
+        match self {
+            LaunchMethod::Unknown => 'U',
+            LaunchMethod::Winch => 'W',
+            LaunchMethod::Aerotow => 'A',
+            LaunchMethod::SelfLaunch => 'S',
+            LaunchMethod::Bungee => 'B',
+        }
+    }
+
+    pub fn from_char(c: char) -> LaunchMethod {
+        match c {
+            'W' => LaunchMethod::Winch,
+            'A' => LaunchMethod::Aerotow,
+            'S' => LaunchMethod::SelfLaunch,
+            'B' => LaunchMethod::Bungee,
+            _ => LaunchMethod::Unknown,
+        }
+    }
+}
+
+// logbook_events.event types besides 'T' (take-off) and 'L' (landing):
+pub const EVENT_TOUCH_AND_GO: char = 'N';
+pub const EVENT_GO_AROUND: char = 'G';
+pub const EVENT_LOW_PASS: char = 'P';
@@ -0,0 +1,177 @@
+//! Pairs take-off and landing logbook_events into logbook_entries (flights).
+//! A landing closes the latest take-off of the same aircraft if that one has not landed yet.
+//! Take-offs and landings without a counterpart are stored as incomplete entries.
+
+use log::debug;
+
+use crate::db::data_structures::{LogbookEvent, LogbookItem};
+use crate::db::logbook_repository::{EntryChange, LogbookRepository, RepositoryResult};
+
+const EB_MAX_FLIGHT_TIME: i64 = 16 * 3600;  // [s] older take-offs are not considered open anymore
+const EB_DUPLICATE_TS_DIFF: i64 = 60;       // [s] the same event reported again (e.g. by the RedisReaper)
+
+#[derive(Debug, PartialEq)]
+pub enum EntryAction {
+    /// new entry with the take-off only
+    Open,
+    /// landing of the open entry with given id
+    Close(u64),
+    /// new entry with the landing only
+    OrphanLanding,
+    /// the event has been stored already
+    Duplicate,
+    /// not a take-off nor a landing
+    Ignore,
+}
+
+/// Decides what a take-off or landing event does with the logbook entries.
+/// @param entries: recent entries of the same aircraft (see EB_MAX_FLIGHT_TIME)
+pub fn pair_event(event: &LogbookEvent, entries: &[LogbookItem]) -> EntryAction {
+    match event.event.as_str() {
+        "T" => {
+            if entries.iter().any(|e| e.takeoff_ts > 0 && (e.takeoff_ts - event.ts).abs() <= EB_DUPLICATE_TS_DIFF) {
+                return EntryAction::Duplicate;
+            }
+
+            EntryAction::Open
+        },
+
+        "L" => {
+            if entries.iter().any(|e| e.landing_ts > 0 && (e.landing_ts - event.ts).abs() <= EB_DUPLICATE_TS_DIFF) {
+                return EntryAction::Duplicate;
+            }
+
+            // only the most recent take-off can be closed - older open ones missed their landing:
+            let latest_takeoff = entries.iter()
+                .filter(|e| e.takeoff_ts > 0 && e.takeoff_ts <= event.ts && event.ts - e.takeoff_ts <= EB_MAX_FLIGHT_TIME)
+                .max_by_key(|e| e.takeoff_ts);
+
+            match latest_takeoff {
+                Some(e) if e.landing_ts == 0 => EntryAction::Close(e.id),
+                _ => EntryAction::OrphanLanding,
+            }
+        },
+
+        _ => EntryAction::Ignore,
+    }
+}
+
+/// Stores the event and opens, closes or creates the related logbook entry.
+pub fn store_event(repository: &mut Box<dyn LogbookRepository>, event: &LogbookEvent) -> RepositoryResult<()> {
+    let entries = match event.event.as_str() {
+        "T" | "L" => repository.list_recent_entries(&event.address, &event.address_type, event.ts - EB_MAX_FLIGHT_TIME)?,
+        _ => vec![],
+    };
+
+    let action = pair_event(event, &entries);
+    if action == EntryAction::Duplicate {
+        debug!("Skipping duplicate event '{}' of {} at {}", event.event, event.address, event.ts);
+        return Ok(());
+    }
+
+    match action {
+        EntryAction::Open => {
+            let mut item = LogbookItem::new(0, event.address.clone(), event.address_type.clone(), event.ts, event.location_icao.clone());
+            item.aircraft_type = event.aircraft_type.clone();
+            item.takeoff_lat = event.lat;
+            item.takeoff_lon = event.lon;
+
+            repository.store_event_tx(event, &EntryChange::Insert(&item))
+        },
+
+        EntryAction::Close(entry_id) => {
+            let takeoff_ts = entries.iter().find(|e| e.id == entry_id).map(|e| e.takeoff_ts).unwrap_or(event.ts);
+            repository.store_event_tx(event, &EntryChange::Close { entry_id, flight_time: event.ts - takeoff_ts })
+        },
+
+        EntryAction::OrphanLanding => {
+            let mut item = LogbookItem::new(0, event.address.clone(), event.address_type.clone(), 0, "".into());
+            item.aircraft_type = event.aircraft_type.clone();
+            item.landing_ts = event.ts;
+            item.landing_lat = event.lat;
+            item.landing_lon = event.lon;
+            item.landing_icao = event.location_icao.clone();
+
+            repository.store_event_tx(event, &EntryChange::Insert(&item))
+        },
+
+        EntryAction::Duplicate | EntryAction::Ignore => repository.store_event_tx(event, &EntryChange::None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ogn_client::data_structures::{AddressType, AircraftType};
+
+    use crate::db::data_structures::{LogbookEvent, LogbookItem};
+
+    use super::{pair_event, EntryAction};
+
+    fn event(event: &str, ts: i64) -> LogbookEvent {
+        LogbookEvent {
+            id: 0,
+            ts,
+            event: event.into(),
+            address: "123456".into(),
+            address_type: AddressType::Ogn,
+            aircraft_type: AircraftType::Glider,
+            lat: 49.0,
+            lon: 16.0,
+            location_icao: "LKKA".into(),
+            flight_time: 0,
+            outlanding: false,
+            elevation: None,
+        }
+    }
+
+    fn entry(id: u64, takeoff_ts: i64, landing_ts: i64) -> LogbookItem {
+        let mut item = LogbookItem::new(id, "123456".into(), AddressType::Ogn, takeoff_ts, "LKKA".into());
+        item.landing_ts = landing_ts;
+        item
+    }
+
+    #[test]
+    fn landing_closes_open_takeoff() {
+        assert_eq!(pair_event(&event("T", 1000), &[]), EntryAction::Open);
+        assert_eq!(pair_event(&event("L", 4000), &[entry(1, 1000, 0)]), EntryAction::Close(1));
+        assert_eq!(pair_event(&event("N", 2000), &[entry(1, 1000, 0)]), EntryAction::Ignore);
+    }
+
+    #[test]
+    fn overlapping_takeoffs() {
+        // landing of the first flight was missed - the landing belongs to the second take-off:
+        let entries = [entry(1, 1000, 0), entry(2, 3000, 0)];
+        assert_eq!(pair_event(&event("L", 5000), &entries), EntryAction::Close(2));
+
+        // a landed flight between an open take-off and the landing:
+        let entries = [entry(1, 1000, 0), entry(2, 3000, 4000)];
+        assert_eq!(pair_event(&event("L", 6000), &entries), EntryAction::OrphanLanding);
+
+        // a take-off after the landing is not its counterpart:
+        let entries = [entry(1, 1000, 2000), entry(2, 7000, 0)];
+        assert_eq!(pair_event(&event("L", 5000), &entries), EntryAction::OrphanLanding);
+    }
+
+    #[test]
+    fn missing_takeoff() {
+        assert_eq!(pair_event(&event("L", 5000), &[]), EntryAction::OrphanLanding);
+
+        // too old to be the same flight:
+        assert_eq!(pair_event(&event("L", 100_000), &[entry(1, 1000, 0)]), EntryAction::OrphanLanding);
+    }
+
+    #[test]
+    fn duplicated_events() {
+        let entries = [entry(1, 1000, 0)];
+        assert_eq!(pair_event(&event("T", 1000), &entries), EntryAction::Duplicate);
+        assert_eq!(pair_event(&event("T", 1030), &entries), EntryAction::Duplicate);
+
+        let entries = [entry(1, 1000, 4000)];
+        assert_eq!(pair_event(&event("L", 4000), &entries), EntryAction::Duplicate);
+        assert_eq!(pair_event(&event("L", 4045), &entries), EntryAction::Duplicate);
+
+        // an orphan landing reported twice:
+        let entries = [entry(1, 0, 4000)];
+        assert_eq!(pair_event(&event("L", 4010), &entries), EntryAction::Duplicate);
+    }
+}
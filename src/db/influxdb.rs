@@ -1,8 +1,105 @@
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
 use rinfluxdb::influxql::blocking::Client;
+use rinfluxdb::influxql::Query;
+use rinfluxdb_influxql::ClientError;
 use url::Url;
 
-use crate::configuration::get_influx_url;
+use crate::configuration::{INFLUX_SERIES_NAME, get_influx_url, get_influx_db_name};
+use crate::db::dataframe::DataFrame;
+use crate::db::track_store::TrackPoint;
+
+lazy_static! {
+    static ref ADDR_RE: Regex = Regex::new(r"^[A-Z]{3}[0-9A-F]{6}$").unwrap();
+}
 
 pub fn get_client() -> Client {
     Client::new(Url::parse(&get_influx_url()).unwrap(), Some(("", ""))).unwrap()
 }
+
+/// The address goes into the query text; anything else than prefix + 6 hex digits is refused.
+fn valid_addr(addr: &str) -> bool {
+    if ADDR_RE.is_match(addr) {
+        return true;
+    }
+
+    warn!("Refused to query influx for invalid address '{}'", addr.escape_default());
+    false
+}
+
+/// Reads out positions of an aircraft between two timestamps ordered by time.
+/// @param addr: ogn ID with prefix OGN/ICA/FLR
+/// @return empty vector if there are no data for the aircraft
+pub fn get_track(client: &Client, addr: &str, start_ts: i64, end_ts: i64) -> Vec<TrackPoint> {
+    if !valid_addr(addr) {
+        return vec![];
+    }
+
+    let influx_db_name = get_influx_db_name();
+
+    let q = format!("SELECT lat, lon, alt, agl, gs, vs FROM {influx_db_name}..{INFLUX_SERIES_NAME} WHERE addr='{addr}' AND time >= {start_ts}000000000 AND time <= {end_ts}000000000 ORDER BY time");
+    fetch_track(client, q)
+}
+
+/// Reads out the most recent positions of an aircraft.
+/// @param addr: ogn ID with prefix OGN/ICA/FLR
+/// @return up to `n` positions, the latest one first
+pub fn get_last_positions(client: &Client, addr: &str, n: usize) -> Vec<TrackPoint> {
+    if !valid_addr(addr) {
+        return vec![];
+    }
+
+    let influx_db_name = get_influx_db_name();
+
+    let q = format!("SELECT lat, lon, alt, agl, gs, vs FROM {influx_db_name}..{INFLUX_SERIES_NAME} WHERE addr='{addr}' ORDER BY time DESC LIMIT {n}");
+    fetch_track(client, q)
+}
+
+fn fetch_track(client: &Client, q: String) -> Vec<TrackPoint> {
+    let res: Result<DataFrame, ClientError> = client.fetch_dataframe(Query::new(q));
+    let df = match res {
+        Ok(df) => df,
+        Err(_) => return vec![],
+    };
+
+    let cols = df.columns;
+    let (latitudes, longitudes, altitudes, agls, ground_speeds, vertical_speeds) =
+        match (cols.get("lat"), cols.get("lon"), cols.get("alt"), cols.get("agl"), cols.get("gs"), cols.get("vs")) {
+            (Some(lat), Some(lon), Some(alt), Some(agl), Some(gs), Some(vs)) => (lat, lon, alt, agl, gs, vs),
+            _ => return vec![],
+        };
+
+    let mut track: Vec<TrackPoint> = Vec::with_capacity(df.index.len());
+    for (i, dt) in df.index.iter().enumerate() {
+        track.push(TrackPoint {
+            ts: dt.timestamp(),
+            lat: latitudes.get_float_value(i).unwrap_or(0_f64),
+            lon: longitudes.get_float_value(i).unwrap_or(0_f64),
+            alt: altitudes.get_int_value(i).unwrap_or(0_i64),
+            agl: agls.get_int_value(i).unwrap_or(0_i64),
+            gs: ground_speeds.get_int_value(i).unwrap_or(0_i64),
+            vs: vertical_speeds.get_float_value(i).unwrap_or(0_f64),
+        });
+    }
+
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::valid_addr;
+
+    #[test]
+    fn addresses_in_queries() {
+        assert!(valid_addr("OGN123456"));
+        assert!(valid_addr("FLRDD02AE"));
+        assert!(valid_addr("ICA4B4E5C"));
+
+        assert!(!valid_addr("FLRdd02ae"));
+        assert!(!valid_addr("OGN12345"));
+        assert!(!valid_addr("OGN1234567"));
+        assert!(!valid_addr("OGN123456' OR addr=~/.*/ --"));
+        assert!(!valid_addr(""));
+    }
+}
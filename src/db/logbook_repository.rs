@@ -0,0 +1,209 @@
+//! Typed access to the logbook tables (logbook_events, logbook_entries, permanent_storage).
+//! All values go to the database as statement parameters, never as a part of the SQL text.
+
+use ogn_client::data_structures::{AddressType, AircraftType};
+
+use crate::configuration::{get_db_type, get_sqlite_filepath};
+use crate::db::data_structures::{LaunchMethod, LogbookEvent, LogbookItem};
+
+pub mod mysql_logbook_repository;
+pub mod postgres_logbook_repository;
+pub mod sqlite_logbook_repository;
+
+use mysql_logbook_repository::MySqlLogbookRepository;
+use postgres_logbook_repository::PostgresLogbookRepository;
+use sqlite_logbook_repository::SqliteLogbookRepository;
+
+pub type RepositoryResult<T> = Result<T, String>;
+
+/// Tow release of a glider; written into both the glider and the tow plane entries.
+#[derive(Debug, Clone)]
+pub struct TowRelease {
+    pub ts: i64,            // UTC [s]
+    pub alt: i64,           // [m] AMSL
+    pub agl: Option<i64>,   // [m]
+    pub duration: i64,      // [s]
+}
+
+/// Change of the logbook entry stored together with its event (see LogbookRepository::store_event_tx()).
+#[derive(Debug)]
+pub enum EntryChange<'a> {
+    None,
+    Insert(&'a LogbookItem),
+    Close { entry_id: u64, flight_time: i64 },     // the landing is the event itself
+}
+
+pub trait LogbookRepository: Send {
+    /// Applies the pending schema migrations (see db::migrations).
+    /// @return versions applied in this run
+    fn migrate(&mut self) -> RepositoryResult<Vec<u32>>;
+
+    /// Stores a take-off, landing or any other flight event.
+    /// Use entry_builder::store_event() to keep the logbook entries in line.
+    fn insert_event(&mut self, event: &LogbookEvent) -> RepositoryResult<()>;
+
+    fn find_latest_takeoff(&mut self, address: &str, address_type: &AddressType) -> RepositoryResult<Option<LogbookEvent>>;
+
+    /// Entries of the aircraft which took off or landed since the given time.
+    fn list_recent_entries(&mut self, address: &str, address_type: &AddressType, since_ts: i64) -> RepositoryResult<Vec<LogbookItem>>;
+
+    /// Stores a new entry with the take-off or the landing part (ts = 0 for the missing one).
+    /// @return id of the new entry
+    fn insert_entry(&mut self, item: &LogbookItem) -> RepositoryResult<u64>;
+
+    /// Fills in the landing of an open entry.
+    /// @param flight_time: [s]
+    fn close_entry(&mut self, entry_id: u64, landing: &LogbookEvent, flight_time: i64) -> RepositoryResult<()>;
+
+    /// Stores the event and the change of its entry in one transaction; nothing is written if any of them fails.
+    fn store_event_tx(&mut self, event: &LogbookEvent, change: &EntryChange) -> RepositoryResult<()>;
+
+    /// Take-offs stored since the given time.
+    fn list_takeoffs_since(&mut self, ts: i64) -> RepositoryResult<Vec<LogbookEvent>>;
+
+    /// Moves a take-off and its logbook entry to the real start of the take-off roll.
+    /// @param location_icao: "" = unknown
+    fn update_takeoff(&mut self, id: u64, ts: i64, lat: f64, lon: f64, location_icao: &str) -> RepositoryResult<()>;
+
+    /// Take-offs and landings at known airfields stored between the given times whose runway has not been looked up yet.
+    fn list_events_without_runway(&mut self, from_ts: i64, to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookEvent>>;
+
+    /// @param runway: "" = looked up but not determined
+    fn set_runway(&mut self, event_id: u64, runway: &str) -> RepositoryResult<()>;
+
+    /// Finished flights landed since the given time whose flown distance has not been calculated yet.
+    fn list_entries_without_distance(&mut self, landed_since_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>>;
+
+    /// @param distance: [km]
+    /// @param max_alt: [m] AMSL
+    fn set_flown_distance(&mut self, entry_id: u64, distance: u64, max_alt: i64) -> RepositoryResult<()>;
+
+    /// Flights of the given aircraft types landed since the given time not looked at by the TowLookup yet.
+    fn list_tow_unchecked_entries(&mut self, landed_since_ts: i64, aircraft_types: &[AircraftType]) -> RepositoryResult<Vec<LogbookItem>>;
+
+    /// Not yet paired flights of the given aircraft types which took off within max_ts_diff from the item
+    /// (from the same airfield if known), ordered by the take-off time difference.
+    fn list_tow_counterparts(&mut self, item: &LogbookItem, aircraft_types: &[AircraftType], max_ts_diff: i64) -> RepositoryResult<Vec<LogbookItem>>;
+
+    /// Marks the entry as looked at by the TowLookup (paired or not).
+    fn set_tow_checked(&mut self, entry_id: u64) -> RepositoryResult<()>;
+
+    /// Links the glider and the tow plane entries to each other (and marks both as checked).
+    fn set_tow_ids(&mut self, glider_id: u64, tow_id: u64) -> RepositoryResult<()>;
+
+    fn set_tow_release(&mut self, glider_id: u64, tow_id: u64, release: &TowRelease) -> RepositoryResult<()>;
+
+    /// Flights without launch method landed between the given times.
+    fn list_unclassified_entries(&mut self, landed_from_ts: i64, landed_to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>>;
+
+    fn set_launch_method(&mut self, entry_id: u64, launch_method: LaunchMethod) -> RepositoryResult<()>;
+
+    /// Addresses whose data are kept permanently.
+    fn list_permanent_storage(&mut self, address_type: &AddressType) -> RepositoryResult<Vec<String>>;
+}
+
+/// @return repository of the logbook database as configured (see configuration::get_db_type())
+pub fn get_logbook_repository() -> RepositoryResult<Box<dyn LogbookRepository>> {
+    match get_db_type().as_str() {
+        "sqlite" => Ok(Box::new(SqliteLogbookRepository::new(&get_sqlite_filepath())?)),
+        "postgres" => Ok(Box::new(PostgresLogbookRepository::new()?)),
+        _ => Ok(Box::new(MySqlLogbookRepository::new()?)),
+    }
+}
+
+/// Backend-agnostic tests; each repository runs them on its own migrated and empty db.
+#[cfg(test)]
+pub(crate) mod suite {
+    use ogn_client::data_structures::{AddressType, AircraftType};
+
+    use crate::db::data_structures::{LaunchMethod, LogbookEvent};
+    use crate::db::entry_builder;
+
+    use super::{LogbookRepository, TowRelease};
+
+    pub(crate) fn event(event: &str, address: &str, aircraft_type: AircraftType, ts: i64) -> LogbookEvent {
+        LogbookEvent {
+            id: 0,
+            ts,
+            event: event.into(),
+            address: address.into(),
+            address_type: AddressType::Ogn,
+            aircraft_type,
+            lat: 49.123456,
+            lon: 16.654321,
+            location_icao: "LKKA".into(),
+            flight_time: 0,
+            outlanding: false,
+            elevation: None,
+        }
+    }
+
+    pub(crate) fn logbook_entries_from_events(mut repository: Box<dyn LogbookRepository>) {
+        entry_builder::store_event(&mut repository, &event("T", "123456", AircraftType::Glider, 1000)).unwrap();
+        entry_builder::store_event(&mut repository, &event("T", "123456", AircraftType::Glider, 1010)).unwrap();   // duplicate
+        entry_builder::store_event(&mut repository, &event("L", "123456", AircraftType::Glider, 4600)).unwrap();
+        entry_builder::store_event(&mut repository, &event("L", "ABCDEF", AircraftType::TowPlane, 1600)).unwrap(); // orphan
+
+        let takeoff = repository.find_latest_takeoff("123456", &AddressType::Ogn).unwrap().unwrap();
+        assert_eq!(takeoff.ts, 1000);
+        assert_eq!((takeoff.lat, takeoff.lon), (49.12346, 16.65432));
+        assert_eq!(takeoff.aircraft_type, AircraftType::Glider);
+        assert_eq!(repository.list_takeoffs_since(0).unwrap().len(), 1);
+
+        let entries = repository.list_recent_entries("123456", &AddressType::Ogn, 0).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].takeoff_ts, entries[0].landing_ts), (1000, 4600));
+        assert_eq!(entries[0].aircraft_type, AircraftType::Glider);
+
+        let orphans = repository.list_recent_entries("ABCDEF", &AddressType::Ogn, 0).unwrap();
+        assert_eq!((orphans[0].takeoff_ts, orphans[0].landing_ts), (0, 1600));
+
+        // the real take-off moves the entry as well:
+        repository.update_takeoff(takeoff.id, 990, 49.1, 16.6, "").unwrap();
+        let takeoff = repository.find_latest_takeoff("123456", &AddressType::Ogn).unwrap().unwrap();
+        assert_eq!((takeoff.ts, takeoff.lat, takeoff.lon), (990, 49.1, 16.6));
+
+        let entries = repository.list_entries_without_distance(0, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].takeoff_ts, 990);
+        assert_eq!(entries[0].takeoff_icao, "");
+
+        repository.set_flown_distance(entries[0].id, 120, 1800).unwrap();
+        assert!(repository.list_entries_without_distance(0, 10).unwrap().is_empty());
+    }
+
+    pub(crate) fn runways_tows_and_launches(mut repository: Box<dyn LogbookRepository>) {
+        for e in [event("T", "123456", AircraftType::Glider, 1000), event("T", "ABCDEF", AircraftType::TowPlane, 1004),
+                  event("L", "ABCDEF", AircraftType::TowPlane, 1600), event("L", "123456", AircraftType::Glider, 4600)] {
+            entry_builder::store_event(&mut repository, &e).unwrap();
+        }
+
+        let events = repository.list_events_without_runway(0, 5000, 100).unwrap();
+        assert_eq!(events.len(), 4);
+        repository.set_runway(events[0].id, "27").unwrap();
+        assert_eq!(repository.list_events_without_runway(0, 5000, 100).unwrap().len(), 3);
+
+        let gliders = repository.list_tow_unchecked_entries(0, &[AircraftType::Glider]).unwrap();
+        assert_eq!(gliders.len(), 1);
+        let tows = repository.list_tow_counterparts(&gliders[0], &[AircraftType::TowPlane, AircraftType::PoweredAircraft], 10).unwrap();
+        assert_eq!(tows.len(), 1);
+        assert_eq!(tows[0].addr, "ABCDEF");
+
+        // a tow plane checked before the glider landed is still a counterpart:
+        repository.set_tow_checked(tows[0].id).unwrap();
+        assert!(repository.list_tow_unchecked_entries(0, &[AircraftType::TowPlane]).unwrap().is_empty());
+        assert_eq!(repository.list_tow_counterparts(&gliders[0], &[AircraftType::TowPlane], 10).unwrap().len(), 1);
+
+        repository.set_tow_ids(gliders[0].id, tows[0].id).unwrap();
+        repository.set_tow_release(gliders[0].id, tows[0].id, &TowRelease { ts: 1300, alt: 900, agl: Some(600), duration: 300 }).unwrap();
+        assert!(repository.list_tow_unchecked_entries(0, &[AircraftType::Glider, AircraftType::TowPlane]).unwrap().is_empty());
+
+        let unclassified = repository.list_unclassified_entries(0, 5000, 100).unwrap();
+        assert_eq!(unclassified.len(), 2);
+        assert!(unclassified.iter().all(|e| e.tow_id > 0 && e.tow_checked));
+        for e in unclassified.iter() {
+            repository.set_launch_method(e.id, LaunchMethod::Aerotow).unwrap();
+        }
+        assert!(repository.list_unclassified_entries(0, 5000, 100).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+use mysql::{Params, Row, TxOpts, Value};
+use mysql::prelude::Queryable;
+
+use ogn_client::data_structures::{AddressType, AircraftType};
+
+use crate::db::data_structures::{LaunchMethod, LogbookEvent, LogbookItem};
+use crate::db::logbook_repository::{EntryChange, LogbookRepository, RepositoryResult, TowRelease};
+use crate::db::migrations::{self, MYSQL_MIGRATIONS};
+use crate::db::mysql::MySQL;
+
+const EVENT_COLUMNS: &str = "id, ts, event, address, address_type, aircraft_type, lat, lon, location_icao";
+const ENTRY_COLUMNS: &str = "id, address, address_type, aircraft_type, takeoff_ts, takeoff_icao, landing_ts, tow_id, tow_checked";
+
+pub struct MySqlLogbookRepository {
+    mysql: MySQL,
+}
+
+impl MySqlLogbookRepository {
+    pub fn new() -> RepositoryResult<MySqlLogbookRepository> {
+        let mysql = MySQL::new().map_err(|e| format!("Could not connect to MySQL: {e}"))?;
+
+        Ok(Self { mysql })
+    }
+
+    fn row_into_event(mut row: Row) -> LogbookEvent {
+        LogbookEvent {
+            id: row.take("id").unwrap(),
+            ts: row.take("ts").unwrap(),
+            event: row.take("event").unwrap(),
+            address: row.take("address").unwrap(),
+            address_type: AddressType::from_short_str(row.take("address_type").unwrap()),
+            aircraft_type: AircraftType::from(row.take::<u8, _>("aircraft_type").unwrap()),
+            lat: row.take("lat").unwrap(),
+            lon: row.take("lon").unwrap(),
+            location_icao: row.take::<Option<String>, _>("location_icao").unwrap().unwrap_or_default(),
+            flight_time: 0,
+            outlanding: false,
+            elevation: None,
+        }
+    }
+
+    fn row_into_item(mut row: Row) -> LogbookItem {
+        let id = row.take("id").unwrap();
+        let addr = row.take("address").unwrap();
+        let addr_type = AddressType::from_short_str(row.take("address_type").unwrap());
+        let takeoff_ts = row.take::<Option<i64>, _>("takeoff_ts").unwrap().unwrap_or(0);
+        let takeoff_icao = row.take::<Option<String>, _>("takeoff_icao").unwrap().unwrap_or_default();
+
+        let mut item = LogbookItem::new(id, addr, addr_type, takeoff_ts, takeoff_icao);
+        item.aircraft_type = AircraftType::from(row.take::<u8, _>("aircraft_type").unwrap());
+        item.landing_ts = row.take::<Option<i64>, _>("landing_ts").unwrap().unwrap_or(0);
+        item.tow_id = row.take::<Option<i64>, _>("tow_id").unwrap().unwrap_or(0);
+        item.tow_checked = row.take::<bool, _>("tow_checked").unwrap();
+
+        item
+    }
+
+    fn exec_drop<P: Into<Params>>(&mut self, sql: &str, params: P) -> RepositoryResult<()> {
+        self.mysql.get_connection().exec_drop(sql, params).map_err(|e| format!("Error when executing '{sql}': {e}"))
+    }
+
+    /// @param conn: a pooled connection or its transaction
+    fn exec_drop_on<Q: Queryable, P: Into<Params>>(conn: &mut Q, sql: &str, params: P) -> RepositoryResult<()> {
+        conn.exec_drop(sql, params).map_err(|e| format!("Error when executing '{sql}': {e}"))
+    }
+
+    fn insert_event_on<Q: Queryable>(conn: &mut Q, event: &LogbookEvent) -> RepositoryResult<()> {
+        MySqlLogbookRepository::exec_drop_on(conn, "INSERT INTO logbook_events \
+            (ts, address, address_type, aircraft_type, event, lat, lon, location_icao, flight_time, outlanding, elevation) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            (event.ts, &event.address, event.address_type.as_short_str(), event.aircraft_type.value(), &event.event,
+            (event.lat * 1e5).round() / 1e5, (event.lon * 1e5).round() / 1e5, MySqlLogbookRepository::empty_as_null(&event.location_icao),
+            event.flight_time, event.outlanding, event.elevation))
+    }
+
+    fn insert_entry_on<Q: Queryable>(conn: &mut Q, item: &LogbookItem) -> RepositoryResult<u64> {
+        let takeoff_ts = if item.takeoff_ts > 0 { Some(item.takeoff_ts) } else { None };
+        let landing_ts = if item.landing_ts > 0 { Some(item.landing_ts) } else { None };
+        let flight_time = takeoff_ts.and(landing_ts).map(|_| item.landing_ts - item.takeoff_ts);
+
+        let takeoff: (Option<f64>, Option<f64>, Option<&str>) = match takeoff_ts {
+            Some(_) => (Some((item.takeoff_lat * 1e5).round() / 1e5), Some((item.takeoff_lon * 1e5).round() / 1e5), MySqlLogbookRepository::empty_as_null(&item.takeoff_icao)),
+            None => (None, None, None),
+        };
+        let landing: (Option<f64>, Option<f64>, Option<&str>) = match landing_ts {
+            Some(_) => (Some((item.landing_lat * 1e5).round() / 1e5), Some((item.landing_lon * 1e5).round() / 1e5), MySqlLogbookRepository::empty_as_null(&item.landing_icao)),
+            None => (None, None, None),
+        };
+
+        let params: Vec<Value> = vec![
+            item.addr.as_str().into(), item.addr_type.as_short_str().into(), item.aircraft_type.value().into(),
+            takeoff_ts.into(), takeoff.0.into(), takeoff.1.into(), takeoff.2.into(),
+            landing_ts.into(), landing.0.into(), landing.1.into(), landing.2.into(),
+            flight_time.into(),
+        ];
+
+        MySqlLogbookRepository::exec_drop_on(conn, "INSERT INTO logbook_entries \
+            (address, address_type, aircraft_type, takeoff_ts, takeoff_lat, takeoff_lon, takeoff_icao, landing_ts, landing_lat, landing_lon, landing_icao, flight_time) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);", params)?;
+
+        // per connection, hence valid within the transaction, too:
+        let sql = "SELECT LAST_INSERT_ID();";
+        let id = conn.query_first::<u64, _>(sql).map_err(|e| format!("Error when executing '{sql}': {e}"))?;
+
+        id.ok_or_else(|| format!("No id of the entry of {}", item.addr))
+    }
+
+    fn close_entry_on<Q: Queryable>(conn: &mut Q, entry_id: u64, landing: &LogbookEvent, flight_time: i64) -> RepositoryResult<()> {
+        MySqlLogbookRepository::exec_drop_on(conn, "UPDATE logbook_entries SET landing_ts = ?, landing_lat = ?, landing_lon = ?, landing_icao = ?, flight_time = ? WHERE id = ?;",
+            (landing.ts, (landing.lat * 1e5).round() / 1e5, (landing.lon * 1e5).round() / 1e5, MySqlLogbookRepository::empty_as_null(&landing.location_icao),
+            flight_time, entry_id))
+    }
+
+    fn exec_map<T, P: Into<Params>>(&mut self, sql: &str, params: P, f: fn(Row) -> T) -> RepositoryResult<Vec<T>> {
+        self.mysql.get_connection().exec_map(sql, params, f).map_err(|e| format!("Error when executing '{sql}': {e}"))
+    }
+
+    fn table_exists(&mut self, table_name: &str) -> RepositoryResult<bool> {
+        let counts = self.exec_map("SELECT COUNT(*) AS cnt FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?;",
+            (table_name,), |mut row: Row| row.take::<u64, _>("cnt").unwrap())?;
+
+        Ok(counts.into_iter().next().unwrap_or(0) > 0)
+    }
+
+    /// Triggers, stored routines and scheduled events of the pre-versioned schema which built logbook_entries
+    /// from logbook_events. Left in place they would write every flight a second time next to entry_builder.
+    /// @return DROP statements of such objects
+    fn legacy_entry_writers(&mut self) -> RepositoryResult<Vec<String>> {
+        let mut drops = self.exec_map("SELECT TRIGGER_NAME AS name FROM information_schema.TRIGGERS \
+            WHERE TRIGGER_SCHEMA = DATABASE() AND EVENT_OBJECT_TABLE IN ('logbook_events', 'logbook_entries');",
+            (), |mut row: Row| format!("DROP TRIGGER `{}`", row.take::<String, _>("name").unwrap()))?;
+
+        drops.extend(self.exec_map("SELECT ROUTINE_TYPE AS type, ROUTINE_NAME AS name FROM information_schema.ROUTINES \
+            WHERE ROUTINE_SCHEMA = DATABASE() AND ROUTINE_DEFINITION LIKE '%logbook_entries%';",
+            (), |mut row: Row| format!("DROP {} `{}`", row.take::<String, _>("type").unwrap(), row.take::<String, _>("name").unwrap()))?);
+
+        drops.extend(self.exec_map("SELECT EVENT_NAME AS name FROM information_schema.EVENTS \
+            WHERE EVENT_SCHEMA = DATABASE() AND EVENT_DEFINITION LIKE '%logbook_entries%';",
+            (), |mut row: Row| format!("DROP EVENT `{}`", row.take::<String, _>("name").unwrap()))?);
+
+        Ok(drops)
+    }
+
+    fn placeholders(n: usize) -> String {
+        vec!["?"; n].join(", ")
+    }
+
+    fn empty_as_null(s: &str) -> Option<&str> {
+        if s.is_empty() { None } else { Some(s) }
+    }
+}
+
+impl LogbookRepository for MySqlLogbookRepository {
+    fn migrate(&mut self) -> RepositoryResult<Vec<u32>> {
+        // a db set up before the schema was versioned already has the initial schema:
+        let baseline = !self.table_exists("schema_migrations")? && self.table_exists("logbook_events")?;
+
+        self.exec_drop("CREATE TABLE IF NOT EXISTS schema_migrations \
+            (version INT UNSIGNED NOT NULL PRIMARY KEY, description VARCHAR(255) NOT NULL, applied_ts BIGINT NOT NULL);", ())?;
+
+        let now = chrono::Utc::now().timestamp();
+        if baseline {
+            let initial = &MYSQL_MIGRATIONS[0];
+            self.exec_drop("INSERT INTO schema_migrations (version, description, applied_ts) VALUES (?, ?, ?);",
+                (initial.version, initial.description, now))?;
+        }
+
+        let applied = self.exec_map("SELECT version FROM schema_migrations;", (), |mut row: Row| row.take::<u32, _>("version").unwrap())?;
+
+        // the entries are built by entry_builder now; refuse to run next to the old db-side builder:
+        let legacy_writers = self.legacy_entry_writers()?;
+        if legacy_writers.len() > 0 {
+            return Err(format!("The logbook entries are still built inside the db; drop these objects first: {};", legacy_writers.join("; ")));
+        }
+
+        // MySQL commits DDL statements implicitly - a failed migration needs to be fixed by hand:
+        let mut newly_applied = Vec::new();
+        for migration in migrations::pending(&MYSQL_MIGRATIONS, &applied) {
+            for sql in migrations::statements(migration.sql) {
+                self.exec_drop(&sql, ()).map_err(|e| format!("Migration {} failed: {e}", migration.version))?;
+            }
+
+            self.exec_drop("INSERT INTO schema_migrations (version, description, applied_ts) VALUES (?, ?, ?);",
+                (migration.version, migration.description, now))?;
+            newly_applied.push(migration.version);
+        }
+
+        Ok(newly_applied)
+    }
+
+    fn insert_event(&mut self, event: &LogbookEvent) -> RepositoryResult<()> {
+        MySqlLogbookRepository::insert_event_on(&mut self.mysql.get_connection(), event)
+    }
+
+    fn find_latest_takeoff(&mut self, address: &str, address_type: &AddressType) -> RepositoryResult<Option<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events \
+            WHERE address = ? AND address_type = ? AND event = 'T' ORDER BY ts DESC LIMIT 1;");
+
+        let events = self.exec_map(&sql, (address, address_type.as_short_str()), MySqlLogbookRepository::row_into_event)?;
+
+        Ok(events.into_iter().next())
+    }
+
+    fn list_recent_entries(&mut self, address: &str, address_type: &AddressType, since_ts: i64) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE address = ? AND address_type = ? AND (takeoff_ts >= ? OR landing_ts >= ?);");
+
+        self.exec_map(&sql, (address, address_type.as_short_str(), since_ts, since_ts), MySqlLogbookRepository::row_into_item)
+    }
+
+    fn insert_entry(&mut self, item: &LogbookItem) -> RepositoryResult<u64> {
+        MySqlLogbookRepository::insert_entry_on(&mut self.mysql.get_connection(), item)
+    }
+
+    fn close_entry(&mut self, entry_id: u64, landing: &LogbookEvent, flight_time: i64) -> RepositoryResult<()> {
+        MySqlLogbookRepository::close_entry_on(&mut self.mysql.get_connection(), entry_id, landing, flight_time)
+    }
+
+    fn store_event_tx(&mut self, event: &LogbookEvent, change: &EntryChange) -> RepositoryResult<()> {
+        // all the statements have to run on the same pooled connection; rolled back when dropped without the commit:
+        let mut conn = self.mysql.get_connection();
+        let mut tx = conn.start_transaction(TxOpts::default()).map_err(|e| e.to_string())?;
+
+        MySqlLogbookRepository::insert_event_on(&mut tx, event)?;
+        match change {
+            EntryChange::None => (),
+            EntryChange::Insert(item) => { MySqlLogbookRepository::insert_entry_on(&mut tx, item)?; },
+            EntryChange::Close { entry_id, flight_time } => MySqlLogbookRepository::close_entry_on(&mut tx, *entry_id, event, *flight_time)?,
+        }
+
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn list_takeoffs_since(&mut self, ts: i64) -> RepositoryResult<Vec<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events WHERE ts >= ? AND event = 'T';");
+
+        self.exec_map(&sql, (ts,), MySqlLogbookRepository::row_into_event)
+    }
+
+    fn update_takeoff(&mut self, id: u64, ts: i64, lat: f64, lon: f64, location_icao: &str) -> RepositoryResult<()> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events WHERE id = ?;");
+        let takeoff = match self.exec_map(&sql, (id,), MySqlLogbookRepository::row_into_event)?.into_iter().next() {
+            Some(takeoff) => takeoff,
+            None => return Err(format!("No take-off with id {id}")),
+        };
+
+        let (lat, lon) = ((lat * 1e5).round() / 1e5, (lon * 1e5).round() / 1e5);
+        let location_icao = MySqlLogbookRepository::empty_as_null(location_icao);
+
+        self.exec_drop("UPDATE logbook_events SET ts = ?, lat = ?, lon = ?, location_icao = ? WHERE id = ?;",
+            (ts, lat, lon, location_icao, id))?;
+
+        self.exec_drop("UPDATE logbook_entries \
+            SET takeoff_ts = ?, takeoff_lat = ?, takeoff_lon = ?, takeoff_icao = ?, \
+                flight_time = CASE WHEN landing_ts IS NULL THEN NULL ELSE landing_ts - ? END \
+            WHERE address = ? AND address_type = ? AND takeoff_ts = ?;",
+            (ts, lat, lon, location_icao, ts, &takeoff.address, takeoff.address_type.as_short_str(), takeoff.ts))
+    }
+
+    fn list_events_without_runway(&mut self, from_ts: i64, to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events \
+            WHERE runway IS NULL AND location_icao IS NOT NULL AND event IN ('T', 'L') AND ts >= ? AND ts <= ? \
+            LIMIT ?;");
+
+        self.exec_map(&sql, (from_ts, to_ts, limit as u64), MySqlLogbookRepository::row_into_event)
+    }
+
+    fn set_runway(&mut self, event_id: u64, runway: &str) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_events SET runway = ? WHERE id = ?;", (runway, event_id))
+    }
+
+    fn list_entries_without_distance(&mut self, landed_since_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE flown_distance IS NULL \
+                AND address IS NOT NULL AND address_type IS NOT NULL AND takeoff_ts IS NOT NULL AND landing_ts IS NOT NULL \
+                AND landing_ts >= ? \
+            LIMIT ?;");
+
+        self.exec_map(&sql, (landed_since_ts, limit as u64), MySqlLogbookRepository::row_into_item)
+    }
+
+    fn set_flown_distance(&mut self, entry_id: u64, distance: u64, max_alt: i64) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_entries SET flown_distance = ?, max_alt = ? WHERE id = ?;", (distance, max_alt, entry_id))
+    }
+
+    fn list_tow_unchecked_entries(&mut self, landed_since_ts: i64, aircraft_types: &[AircraftType]) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE tow_checked = FALSE AND tow_id IS NULL AND takeoff_ts IS NOT NULL AND landing_ts >= ? AND aircraft_type IN ({});",
+            MySqlLogbookRepository::placeholders(aircraft_types.len()));
+
+        let mut params: Vec<Value> = vec![landed_since_ts.into()];
+        params.extend(aircraft_types.iter().map(|at| Value::from(at.value())));
+
+        self.exec_map(&sql, params, MySqlLogbookRepository::row_into_item)
+    }
+
+    fn list_tow_counterparts(&mut self, item: &LogbookItem, aircraft_types: &[AircraftType], max_ts_diff: i64) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE tow_id IS NULL AND id != ? AND takeoff_ts >= ? AND takeoff_ts <= ? AND aircraft_type IN ({}) \
+                AND (? = '' OR takeoff_icao = ?) \
+            ORDER BY ABS(takeoff_ts - ?);",
+            MySqlLogbookRepository::placeholders(aircraft_types.len()));
+
+        let mut params: Vec<Value> = vec![item.id.into(), (item.takeoff_ts - max_ts_diff).into(), (item.takeoff_ts + max_ts_diff).into()];
+        params.extend(aircraft_types.iter().map(|at| Value::from(at.value())));
+        params.extend([item.takeoff_icao.as_str().into(), item.takeoff_icao.as_str().into(), item.takeoff_ts.into()]);
+
+        self.exec_map(&sql, params, MySqlLogbookRepository::row_into_item)
+    }
+
+    fn set_tow_checked(&mut self, entry_id: u64) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_entries SET tow_checked = TRUE WHERE id = ?;", (entry_id,))
+    }
+
+    fn set_tow_ids(&mut self, glider_id: u64, tow_id: u64) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_entries SET tow_id = ?, tow_checked = TRUE WHERE id = ?;", (tow_id, glider_id))?;
+        self.exec_drop("UPDATE logbook_entries SET tow_id = ?, tow_checked = TRUE WHERE id = ?;", (glider_id, tow_id))
+    }
+
+    fn set_tow_release(&mut self, glider_id: u64, tow_id: u64, release: &TowRelease) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_entries SET release_ts = ?, release_alt = ?, release_agl = ?, tow_duration = ? WHERE id IN (?, ?);",
+            (release.ts, release.alt, release.agl, release.duration, glider_id, tow_id))
+    }
+
+    fn list_unclassified_entries(&mut self, landed_from_ts: i64, landed_to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE launch_method IS NULL AND takeoff_ts IS NOT NULL AND landing_ts >= ? AND landing_ts <= ? \
+            LIMIT ?;");
+
+        self.exec_map(&sql, (landed_from_ts, landed_to_ts, limit as u64), MySqlLogbookRepository::row_into_item)
+    }
+
+    fn set_launch_method(&mut self, entry_id: u64, launch_method: LaunchMethod) -> RepositoryResult<()> {
+        self.exec_drop("UPDATE logbook_entries SET launch_method = ? WHERE id = ?;", (launch_method.as_char().to_string(), entry_id))
+    }
+
+    fn list_permanent_storage(&mut self, address_type: &AddressType) -> RepositoryResult<Vec<String>> {
+        self.exec_map("SELECT addr FROM permanent_storage WHERE addr_type = ? AND active = true;",
+            (address_type.as_short_str(),),
+            |mut row: Row| row.take("addr").unwrap())
+    }
+}
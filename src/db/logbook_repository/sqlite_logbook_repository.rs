@@ -0,0 +1,385 @@
+use std::time::Duration;
+
+use rusqlite::{params, params_from_iter, Connection, Params, Row};
+use rusqlite::types::Value;
+
+use ogn_client::data_structures::{AddressType, AircraftType};
+
+use crate::db::data_structures::{LaunchMethod, LogbookEvent, LogbookItem};
+use crate::db::logbook_repository::{EntryChange, LogbookRepository, RepositoryResult, TowRelease};
+use crate::db::migrations::{self, SQLITE_MIGRATIONS};
+
+const EVENT_COLUMNS: &str = "id, ts, event, address, address_type, aircraft_type, lat, lon, location_icao";
+const ENTRY_COLUMNS: &str = "id, address, address_type, aircraft_type, takeoff_ts, takeoff_icao, landing_ts, tow_id, tow_checked";
+
+/// Opens the SQLite file shared by the logbook repository and the track store.
+pub fn open_connection(filepath: &str) -> RepositoryResult<Connection> {
+    let conn = Connection::open(filepath).map_err(|e| format!("Could not open SQLite db '{filepath}': {e}"))?;
+
+    // the workers, the db threads and the cron jobs have a connection each:
+    conn.busy_timeout(Duration::from_secs(10)).map_err(|e| e.to_string())?;
+    conn.query_row("PRAGMA journal_mode = WAL;", [], |row| row.get::<_, String>(0)).map_err(|e| e.to_string())?;
+    conn.execute_batch("PRAGMA synchronous = NORMAL;").map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+pub struct SqliteLogbookRepository {
+    conn: Connection,
+}
+
+impl SqliteLogbookRepository {
+    pub fn new(filepath: &str) -> RepositoryResult<SqliteLogbookRepository> {
+        Ok(Self { conn: open_connection(filepath)? })
+    }
+
+    fn row_into_event(row: &Row) -> rusqlite::Result<LogbookEvent> {
+        Ok(LogbookEvent {
+            id: row.get::<_, i64>("id")? as u64,
+            ts: row.get("ts")?,
+            event: row.get("event")?,
+            address: row.get("address")?,
+            address_type: AddressType::from_short_str(row.get("address_type")?),
+            aircraft_type: AircraftType::from(row.get::<_, u8>("aircraft_type")?),
+            lat: row.get("lat")?,
+            lon: row.get("lon")?,
+            location_icao: row.get::<_, Option<String>>("location_icao")?.unwrap_or_default(),
+            flight_time: 0,
+            outlanding: false,
+            elevation: None,
+        })
+    }
+
+    fn row_into_item(row: &Row) -> rusqlite::Result<LogbookItem> {
+        let id = row.get::<_, i64>("id")? as u64;
+        let addr = row.get("address")?;
+        let addr_type = AddressType::from_short_str(row.get("address_type")?);
+        let takeoff_ts = row.get::<_, Option<i64>>("takeoff_ts")?.unwrap_or(0);
+        let takeoff_icao = row.get::<_, Option<String>>("takeoff_icao")?.unwrap_or_default();
+
+        let mut item = LogbookItem::new(id, addr, addr_type, takeoff_ts, takeoff_icao);
+        item.aircraft_type = AircraftType::from(row.get::<_, u8>("aircraft_type")?);
+        item.landing_ts = row.get::<_, Option<i64>>("landing_ts")?.unwrap_or(0);
+        item.tow_id = row.get::<_, Option<i64>>("tow_id")?.unwrap_or(0);
+        item.tow_checked = row.get("tow_checked")?;
+
+        Ok(item)
+    }
+
+    fn execute<P: Params>(&self, sql: &str, params: P) -> RepositoryResult<usize> {
+        SqliteLogbookRepository::execute_on(&self.conn, sql, params)
+    }
+
+    /// @param conn: the connection itself or its transaction
+    fn execute_on<P: Params>(conn: &Connection, sql: &str, params: P) -> RepositoryResult<usize> {
+        conn.execute(sql, params).map_err(|e| format!("Error when executing '{sql}': {e}"))
+    }
+
+    fn insert_event_on(conn: &Connection, event: &LogbookEvent) -> RepositoryResult<()> {
+        SqliteLogbookRepository::execute_on(conn, "INSERT INTO logbook_events \
+            (ts, address, address_type, aircraft_type, event, lat, lon, location_icao, flight_time, outlanding, elevation) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            params![event.ts, event.address, event.address_type.as_short_str(), event.aircraft_type.value(), event.event,
+            SqliteLogbookRepository::round(event.lat), SqliteLogbookRepository::round(event.lon), SqliteLogbookRepository::empty_as_null(&event.location_icao),
+            event.flight_time, event.outlanding, event.elevation])?;
+
+        Ok(())
+    }
+
+    fn insert_entry_on(conn: &Connection, item: &LogbookItem) -> RepositoryResult<u64> {
+        let takeoff = item.takeoff_ts > 0;
+        let landing = item.landing_ts > 0;
+
+        SqliteLogbookRepository::execute_on(conn, "INSERT INTO logbook_entries \
+            (address, address_type, aircraft_type, takeoff_ts, takeoff_lat, takeoff_lon, takeoff_icao, landing_ts, landing_lat, landing_lon, landing_icao, flight_time) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            params![item.addr, item.addr_type.as_short_str(), item.aircraft_type.value(),
+            takeoff.then_some(item.takeoff_ts), takeoff.then_some(SqliteLogbookRepository::round(item.takeoff_lat)),
+            takeoff.then_some(SqliteLogbookRepository::round(item.takeoff_lon)), takeoff.then(|| SqliteLogbookRepository::empty_as_null(&item.takeoff_icao)).flatten(),
+            landing.then_some(item.landing_ts), landing.then_some(SqliteLogbookRepository::round(item.landing_lat)),
+            landing.then_some(SqliteLogbookRepository::round(item.landing_lon)), landing.then(|| SqliteLogbookRepository::empty_as_null(&item.landing_icao)).flatten(),
+            (takeoff && landing).then_some(item.landing_ts - item.takeoff_ts)])?;
+
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    fn close_entry_on(conn: &Connection, entry_id: u64, landing: &LogbookEvent, flight_time: i64) -> RepositoryResult<()> {
+        SqliteLogbookRepository::execute_on(conn, "UPDATE logbook_entries SET landing_ts = ?, landing_lat = ?, landing_lon = ?, landing_icao = ?, flight_time = ? WHERE id = ?;",
+            params![landing.ts, SqliteLogbookRepository::round(landing.lat), SqliteLogbookRepository::round(landing.lon),
+            SqliteLogbookRepository::empty_as_null(&landing.location_icao), flight_time, entry_id as i64])?;
+
+        Ok(())
+    }
+
+    fn query_map<T, P: Params>(&self, sql: &str, params: P, f: fn(&Row) -> rusqlite::Result<T>) -> RepositoryResult<Vec<T>> {
+        let mut stmt = self.conn.prepare_cached(sql).map_err(|e| format!("Error when preparing '{sql}': {e}"))?;
+        let rows = stmt.query_map(params, f)
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<T>>>())
+            .map_err(|e| format!("Error when executing '{sql}': {e}"));
+
+        rows
+    }
+
+    fn placeholders(n: usize) -> String {
+        vec!["?"; n].join(", ")
+    }
+
+    fn empty_as_null(s: &str) -> Option<&str> {
+        if s.is_empty() { None } else { Some(s) }
+    }
+
+    fn round(deg: f64) -> f64 {
+        (deg * 1e5).round() / 1e5
+    }
+}
+
+impl LogbookRepository for SqliteLogbookRepository {
+    fn migrate(&mut self) -> RepositoryResult<Vec<u32>> {
+        self.execute("CREATE TABLE IF NOT EXISTS schema_migrations \
+            (version INTEGER NOT NULL PRIMARY KEY, description TEXT NOT NULL, applied_ts INTEGER NOT NULL);", [])?;
+
+        let applied = self.query_map("SELECT version FROM schema_migrations;", [], |row| row.get::<_, u32>("version"))?;
+
+        let now = chrono::Utc::now().timestamp();
+        let mut newly_applied = Vec::new();
+        for migration in migrations::pending(&SQLITE_MIGRATIONS, &applied) {
+            // unlike MySQL, SQLite rolls back the DDL of a failed migration:
+            let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+            for sql in migrations::statements(migration.sql) {
+                tx.execute(&sql, []).map_err(|e| format!("Migration {} failed: {e}", migration.version))?;
+            }
+            tx.execute("INSERT INTO schema_migrations (version, description, applied_ts) VALUES (?, ?, ?);",
+                params![migration.version, migration.description, now]).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+
+            newly_applied.push(migration.version);
+        }
+
+        Ok(newly_applied)
+    }
+
+    fn insert_event(&mut self, event: &LogbookEvent) -> RepositoryResult<()> {
+        SqliteLogbookRepository::insert_event_on(&self.conn, event)
+    }
+
+    fn find_latest_takeoff(&mut self, address: &str, address_type: &AddressType) -> RepositoryResult<Option<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events \
+            WHERE address = ? AND address_type = ? AND event = 'T' ORDER BY ts DESC LIMIT 1;");
+
+        let events = self.query_map(&sql, params![address, address_type.as_short_str()], SqliteLogbookRepository::row_into_event)?;
+
+        Ok(events.into_iter().next())
+    }
+
+    fn list_recent_entries(&mut self, address: &str, address_type: &AddressType, since_ts: i64) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE address = ? AND address_type = ? AND (takeoff_ts >= ? OR landing_ts >= ?);");
+
+        self.query_map(&sql, params![address, address_type.as_short_str(), since_ts, since_ts], SqliteLogbookRepository::row_into_item)
+    }
+
+    fn insert_entry(&mut self, item: &LogbookItem) -> RepositoryResult<u64> {
+        SqliteLogbookRepository::insert_entry_on(&self.conn, item)
+    }
+
+    fn close_entry(&mut self, entry_id: u64, landing: &LogbookEvent, flight_time: i64) -> RepositoryResult<()> {
+        SqliteLogbookRepository::close_entry_on(&self.conn, entry_id, landing, flight_time)
+    }
+
+    fn store_event_tx(&mut self, event: &LogbookEvent, change: &EntryChange) -> RepositoryResult<()> {
+        // rolled back when dropped without the commit:
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+
+        SqliteLogbookRepository::insert_event_on(&tx, event)?;
+        match change {
+            EntryChange::None => (),
+            EntryChange::Insert(item) => { SqliteLogbookRepository::insert_entry_on(&tx, item)?; },
+            EntryChange::Close { entry_id, flight_time } => SqliteLogbookRepository::close_entry_on(&tx, *entry_id, event, *flight_time)?,
+        }
+
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn list_takeoffs_since(&mut self, ts: i64) -> RepositoryResult<Vec<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events WHERE ts >= ? AND event = 'T';");
+
+        self.query_map(&sql, params![ts], SqliteLogbookRepository::row_into_event)
+    }
+
+    fn update_takeoff(&mut self, id: u64, ts: i64, lat: f64, lon: f64, location_icao: &str) -> RepositoryResult<()> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events WHERE id = ?;");
+        let takeoff = match self.query_map(&sql, params![id as i64], SqliteLogbookRepository::row_into_event)?.into_iter().next() {
+            Some(takeoff) => takeoff,
+            None => return Err(format!("No take-off with id {id}")),
+        };
+
+        let (lat, lon) = (SqliteLogbookRepository::round(lat), SqliteLogbookRepository::round(lon));
+        let location_icao = SqliteLogbookRepository::empty_as_null(location_icao);
+
+        self.execute("UPDATE logbook_events SET ts = ?, lat = ?, lon = ?, location_icao = ? WHERE id = ?;",
+            params![ts, lat, lon, location_icao, id as i64])?;
+
+        self.execute("UPDATE logbook_entries \
+            SET takeoff_ts = ?, takeoff_lat = ?, takeoff_lon = ?, takeoff_icao = ?, \
+                flight_time = CASE WHEN landing_ts IS NULL THEN NULL ELSE landing_ts - ? END \
+            WHERE address = ? AND address_type = ? AND takeoff_ts = ?;",
+            params![ts, lat, lon, location_icao, ts, takeoff.address, takeoff.address_type.as_short_str(), takeoff.ts])?;
+
+        Ok(())
+    }
+
+    fn list_events_without_runway(&mut self, from_ts: i64, to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookEvent>> {
+        let sql = format!("SELECT {EVENT_COLUMNS} FROM logbook_events \
+            WHERE runway IS NULL AND location_icao IS NOT NULL AND event IN ('T', 'L') AND ts >= ? AND ts <= ? \
+            LIMIT ?;");
+
+        self.query_map(&sql, params![from_ts, to_ts, limit as i64], SqliteLogbookRepository::row_into_event)
+    }
+
+    fn set_runway(&mut self, event_id: u64, runway: &str) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_events SET runway = ? WHERE id = ?;", params![runway, event_id as i64])?;
+
+        Ok(())
+    }
+
+    fn list_entries_without_distance(&mut self, landed_since_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE flown_distance IS NULL \
+                AND address IS NOT NULL AND address_type IS NOT NULL AND takeoff_ts IS NOT NULL AND landing_ts IS NOT NULL \
+                AND landing_ts >= ? \
+            LIMIT ?;");
+
+        self.query_map(&sql, params![landed_since_ts, limit as i64], SqliteLogbookRepository::row_into_item)
+    }
+
+    fn set_flown_distance(&mut self, entry_id: u64, distance: u64, max_alt: i64) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_entries SET flown_distance = ?, max_alt = ? WHERE id = ?;",
+            params![distance as i64, max_alt, entry_id as i64])?;
+
+        Ok(())
+    }
+
+    fn list_tow_unchecked_entries(&mut self, landed_since_ts: i64, aircraft_types: &[AircraftType]) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE tow_checked = 0 AND tow_id IS NULL AND takeoff_ts IS NOT NULL AND landing_ts >= ? AND aircraft_type IN ({});",
+            SqliteLogbookRepository::placeholders(aircraft_types.len()));
+
+        let mut params: Vec<Value> = vec![landed_since_ts.into()];
+        params.extend(aircraft_types.iter().map(|at| Value::from(at.value() as i64)));
+
+        self.query_map(&sql, params_from_iter(params), SqliteLogbookRepository::row_into_item)
+    }
+
+    fn list_tow_counterparts(&mut self, item: &LogbookItem, aircraft_types: &[AircraftType], max_ts_diff: i64) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE tow_id IS NULL AND id != ? AND takeoff_ts >= ? AND takeoff_ts <= ? AND aircraft_type IN ({}) \
+                AND (? = '' OR takeoff_icao = ?) \
+            ORDER BY ABS(takeoff_ts - ?);",
+            SqliteLogbookRepository::placeholders(aircraft_types.len()));
+
+        let mut params: Vec<Value> = vec![(item.id as i64).into(), (item.takeoff_ts - max_ts_diff).into(), (item.takeoff_ts + max_ts_diff).into()];
+        params.extend(aircraft_types.iter().map(|at| Value::from(at.value() as i64)));
+        params.extend([item.takeoff_icao.clone().into(), item.takeoff_icao.clone().into(), item.takeoff_ts.into()]);
+
+        self.query_map(&sql, params_from_iter(params), SqliteLogbookRepository::row_into_item)
+    }
+
+    fn set_tow_checked(&mut self, entry_id: u64) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_entries SET tow_checked = 1 WHERE id = ?;", params![entry_id as i64])?;
+
+        Ok(())
+    }
+
+    fn set_tow_ids(&mut self, glider_id: u64, tow_id: u64) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_entries SET tow_id = ?, tow_checked = 1 WHERE id = ?;", params![tow_id as i64, glider_id as i64])?;
+        self.execute("UPDATE logbook_entries SET tow_id = ?, tow_checked = 1 WHERE id = ?;", params![glider_id as i64, tow_id as i64])?;
+
+        Ok(())
+    }
+
+    fn set_tow_release(&mut self, glider_id: u64, tow_id: u64, release: &TowRelease) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_entries SET release_ts = ?, release_alt = ?, release_agl = ?, tow_duration = ? WHERE id IN (?, ?);",
+            params![release.ts, release.alt, release.agl, release.duration, glider_id as i64, tow_id as i64])?;
+
+        Ok(())
+    }
+
+    fn list_unclassified_entries(&mut self, landed_from_ts: i64, landed_to_ts: i64, limit: usize) -> RepositoryResult<Vec<LogbookItem>> {
+        let sql = format!("SELECT {ENTRY_COLUMNS} FROM logbook_entries \
+            WHERE launch_method IS NULL AND takeoff_ts IS NOT NULL AND landing_ts >= ? AND landing_ts <= ? \
+            LIMIT ?;");
+
+        self.query_map(&sql, params![landed_from_ts, landed_to_ts, limit as i64], SqliteLogbookRepository::row_into_item)
+    }
+
+    fn set_launch_method(&mut self, entry_id: u64, launch_method: LaunchMethod) -> RepositoryResult<()> {
+        self.execute("UPDATE logbook_entries SET launch_method = ? WHERE id = ?;",
+            params![launch_method.as_char().to_string(), entry_id as i64])?;
+
+        Ok(())
+    }
+
+    fn list_permanent_storage(&mut self, address_type: &AddressType) -> RepositoryResult<Vec<String>> {
+        self.query_map("SELECT addr FROM permanent_storage WHERE addr_type = ? AND active = 1;",
+            params![address_type.as_short_str()],
+            |row| row.get("addr"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+
+    use ogn_client::data_structures::{AddressType, AircraftType};
+
+    use crate::db::data_structures::LogbookItem;
+    use crate::db::logbook_repository::{EntryChange, LogbookRepository};
+    use crate::db::logbook_repository::suite::{self, event};
+
+    use super::SqliteLogbookRepository;
+
+    fn repository() -> Box<dyn LogbookRepository> {
+        let mut repository = SqliteLogbookRepository::new(":memory:").unwrap();
+        assert_eq!(repository.migrate().unwrap(), vec![1, 2]);
+        assert!(repository.migrate().unwrap().is_empty());
+
+        Box::new(repository)
+    }
+
+    #[test]
+    fn logbook_entries_from_events() {
+        suite::logbook_entries_from_events(repository());
+    }
+
+    #[test]
+    fn runways_tows_and_launches() {
+        suite::runways_tows_and_launches(repository());
+    }
+
+    #[test]
+    fn failed_entry_rolls_back_the_event() {
+        let mut repository = SqliteLogbookRepository::new(":memory:").unwrap();
+        repository.migrate().unwrap();
+        repository.execute("DROP TABLE logbook_entries;", []).unwrap();
+
+        let takeoff = event("T", "123456", AircraftType::Glider, 1000);
+        let item = LogbookItem::new(0, takeoff.address.clone(), takeoff.address_type.clone(), takeoff.ts, takeoff.location_icao.clone());
+        assert!(repository.store_event_tx(&takeoff, &EntryChange::Insert(&item)).is_err());
+        assert!(repository.find_latest_takeoff("123456", &AddressType::Ogn).unwrap().is_none());
+
+        repository.store_event_tx(&takeoff, &EntryChange::None).unwrap();
+        assert!(repository.find_latest_takeoff("123456", &AddressType::Ogn).unwrap().is_some());
+    }
+
+    #[test]
+    fn permanent_storage() {
+        let mut repository = SqliteLogbookRepository::new(":memory:").unwrap();
+        repository.migrate().unwrap();
+        for (addr, addr_type, active) in [("C35001", AddressType::Ogn, true), ("C35002", AddressType::Ogn, false), ("DD1234", AddressType::Flarm, true)] {
+            repository.execute("INSERT INTO permanent_storage (addr, addr_type, active) VALUES (?, ?, ?);",
+                params![addr, addr_type.as_short_str(), active]).unwrap();
+        }
+
+        assert_eq!(repository.list_permanent_storage(&AddressType::Ogn).unwrap(), vec!["C35001".to_string()]);
+    }
+}
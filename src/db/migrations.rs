@@ -0,0 +1,140 @@
+//! Versioned schema of the logbook database. The migrations are embedded in the binary
+//! and the applied versions are recorded in the schema_migrations table.
+//!
+//! A migration file is a list of statements separated by ';'. Quoted strings and identifiers may contain
+//! ';' and '--' (a quote inside is doubled: 'it''s'); `--` comments may end any line. Not supported:
+//! /* block comments */, backslash escapes and compound statements (procedure or trigger bodies).
+
+use log::info;
+
+use crate::db::logbook_repository::{self, RepositoryResult};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+pub static MYSQL_MIGRATIONS: [Migration; 4] = [
+    Migration { version: 1, description: "initial schema", sql: include_str!("migrations/mysql/001_initial_schema.sql") },
+    Migration { version: 2, description: "flight details", sql: include_str!("migrations/mysql/002_flight_details.sql") },
+    Migration { version: 3, description: "indexes", sql: include_str!("migrations/mysql/003_indexes.sql") },
+    Migration { version: 4, description: "tow checked", sql: include_str!("migrations/mysql/004_tow_checked.sql") },
+];
+
+pub static SQLITE_MIGRATIONS: [Migration; 2] = [
+    Migration { version: 1, description: "initial schema", sql: include_str!("migrations/sqlite/001_initial_schema.sql") },
+    Migration { version: 2, description: "tow checked", sql: include_str!("migrations/sqlite/002_tow_checked.sql") },
+];
+
+pub static POSTGRES_MIGRATIONS: [Migration; 2] = [
+    Migration { version: 1, description: "initial schema", sql: include_str!("migrations/postgres/001_initial_schema.sql") },
+    Migration { version: 2, description: "tow checked", sql: include_str!("migrations/postgres/002_tow_checked.sql") },
+];
+
+/// Splits a migration into separate statements on ';' outside of quotes; drops the comments.
+pub fn statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                current.push(c);
+                if c == q { quote = None; }    // a doubled quote closes and reopens the string
+            },
+            None => match c {
+                '\'' | '"' | '`' => {
+                    current.push(c);
+                    quote = Some(c);
+                },
+                '-' if chars.peek() == Some(&'-') => {
+                    // comment up to the end of the line:
+                    while chars.peek().map_or(false, |next| *next != '\n') {
+                        chars.next();
+                    }
+                },
+                ';' => statements.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            },
+        }
+    }
+    statements.push(current);
+
+    statements.into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// @return migrations not applied yet in the order of their versions
+pub fn pending<'a>(migrations: &'a [Migration], applied: &[u32]) -> Vec<&'a Migration> {
+    let mut pending: Vec<&Migration> = migrations.iter().filter(|m| !applied.contains(&m.version)).collect();
+    pending.sort_by_key(|m| m.version);
+
+    pending
+}
+
+/// Brings the logbook database schema up to date.
+/// @return versions applied in this run
+pub fn migrate() -> RepositoryResult<Vec<u32>> {
+    let mut repository = logbook_repository::get_logbook_repository()?;
+    let applied = repository.migrate()?;
+
+    if applied.len() > 0 {
+        info!("Applied schema migrations: {applied:?}");
+    } else {
+        info!("The db schema is up to date.");
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pending, statements, Migration, MYSQL_MIGRATIONS, POSTGRES_MIGRATIONS, SQLITE_MIGRATIONS};
+
+    #[test]
+    fn split_statements() {
+        let sql = "-- comment; with a semicolon\nCREATE TABLE a (id INT);\n\n  CREATE INDEX i ON a (id);\n";
+        assert_eq!(statements(sql), vec!["CREATE TABLE a (id INT)", "CREATE INDEX i ON a (id)"]);
+    }
+
+    #[test]
+    fn quotes_and_inline_comments() {
+        let sql = "INSERT INTO t (a, b) VALUES ('x;y', 'it''s'); -- trailing; comment\n\
+            UPDATE t SET a = 'a--b' WHERE `c;` = \"q;\";  -- done\n\
+            -- the end";
+        assert_eq!(statements(sql), vec![
+            "INSERT INTO t (a, b) VALUES ('x;y', 'it''s')",
+            "UPDATE t SET a = 'a--b' WHERE `c;` = \"q;\"",
+        ]);
+    }
+
+    #[test]
+    fn pending_in_version_order() {
+        let migrations = [
+            Migration { version: 3, description: "c", sql: "" },
+            Migration { version: 1, description: "a", sql: "" },
+            Migration { version: 2, description: "b", sql: "" },
+        ];
+
+        let versions: Vec<u32> = pending(&migrations, &[1]).iter().map(|m| m.version).collect();
+        assert_eq!(versions, vec![2, 3]);
+        assert!(pending(&migrations, &[1, 2, 3]).is_empty());
+    }
+
+    #[test]
+    fn migrations_are_numbered() {
+        for migrations in [&MYSQL_MIGRATIONS[..], &SQLITE_MIGRATIONS[..], &POSTGRES_MIGRATIONS[..]] {
+            for (i, m) in migrations.iter().enumerate() {
+                assert_eq!(m.version, i as u32 + 1);
+                assert!(statements(m.sql).len() > 0);
+                // an unterminated quote would swallow the rest of the file:
+                assert!(statements(m.sql).iter().all(|s| !s.contains(';') && !s.contains("--")), "migration {}", m.version);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+-- Tables as used by the logbook before the schema was versioned.
+
+CREATE TABLE IF NOT EXISTS logbook_events (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    ts BIGINT NOT NULL,
+    address VARCHAR(16) NOT NULL,
+    address_type VARCHAR(4) NOT NULL,
+    aircraft_type TINYINT UNSIGNED NOT NULL,
+    event CHAR(1) NOT NULL,
+    lat DOUBLE NOT NULL,
+    lon DOUBLE NOT NULL,
+    location_icao VARCHAR(16) NULL,
+    flight_time BIGINT NULL
+);
+
+CREATE TABLE IF NOT EXISTS logbook_entries (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    address VARCHAR(16) NOT NULL,
+    address_type VARCHAR(4) NOT NULL,
+    aircraft_type TINYINT UNSIGNED NOT NULL,
+    takeoff_ts BIGINT NULL,
+    takeoff_lat DOUBLE NULL,
+    takeoff_lon DOUBLE NULL,
+    takeoff_icao VARCHAR(16) NULL,
+    landing_ts BIGINT NULL,
+    landing_lat DOUBLE NULL,
+    landing_lon DOUBLE NULL,
+    landing_icao VARCHAR(16) NULL,
+    flight_time BIGINT NULL,
+    flown_distance INT UNSIGNED NULL,
+    max_alt INT NULL,
+    device_type VARCHAR(8) NULL,
+    registration VARCHAR(16) NULL,
+    cn VARCHAR(8) NULL,
+    tow_id BIGINT UNSIGNED NULL
+);
+
+CREATE TABLE IF NOT EXISTS permanent_storage (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    addr VARCHAR(16) NOT NULL,
+    addr_type VARCHAR(4) NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE
+);
@@ -0,0 +1,13 @@
+-- Outlandings, runways, launch methods and aerotow releases.
+
+ALTER TABLE logbook_events
+    ADD COLUMN outlanding BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN elevation INT NULL,
+    ADD COLUMN runway VARCHAR(8) NULL;
+
+ALTER TABLE logbook_entries
+    ADD COLUMN launch_method CHAR(1) NULL,
+    ADD COLUMN release_ts BIGINT NULL,
+    ADD COLUMN release_alt INT NULL,
+    ADD COLUMN release_agl INT NULL,
+    ADD COLUMN tow_duration INT NULL;
@@ -0,0 +1,10 @@
+-- RedisReaper (latest take-off of an aircraft), RealTakeoffLookup & RunwayLookup (recent events)
+-- and the entry builder (recent entries of an aircraft).
+
+CREATE INDEX idx_events_address_event_ts ON logbook_events (address, address_type, event, ts);
+CREATE INDEX idx_events_event_ts ON logbook_events (event, ts);
+
+CREATE INDEX idx_entries_address_takeoff_ts ON logbook_entries (address, address_type, takeoff_ts);
+CREATE INDEX idx_entries_landing_ts ON logbook_entries (landing_ts);
+
+CREATE INDEX idx_permanent_storage_addr_type ON permanent_storage (addr_type, active);
@@ -0,0 +1,61 @@
+-- Logbook with the take-off & landing positions as PostGIS points (WGS84).
+
+CREATE EXTENSION IF NOT EXISTS postgis;
+
+CREATE TABLE IF NOT EXISTS logbook_events (
+    id BIGSERIAL PRIMARY KEY,
+    ts BIGINT NOT NULL,
+    address VARCHAR(16) NOT NULL,
+    address_type VARCHAR(4) NOT NULL,
+    aircraft_type SMALLINT NOT NULL,
+    event CHAR(1) NOT NULL,
+    location geometry(Point, 4326) NOT NULL,
+    location_icao VARCHAR(16) NULL,
+    flight_time BIGINT NULL,
+    outlanding BOOLEAN NOT NULL DEFAULT FALSE,
+    elevation BIGINT NULL,
+    runway VARCHAR(8) NULL
+);
+
+CREATE TABLE IF NOT EXISTS logbook_entries (
+    id BIGSERIAL PRIMARY KEY,
+    address VARCHAR(16) NOT NULL,
+    address_type VARCHAR(4) NOT NULL,
+    aircraft_type SMALLINT NOT NULL,
+    takeoff_ts BIGINT NULL,
+    takeoff_location geometry(Point, 4326) NULL,
+    takeoff_icao VARCHAR(16) NULL,
+    landing_ts BIGINT NULL,
+    landing_location geometry(Point, 4326) NULL,
+    landing_icao VARCHAR(16) NULL,
+    flight_time BIGINT NULL,
+    flown_distance BIGINT NULL,
+    max_alt BIGINT NULL,
+    device_type VARCHAR(8) NULL,
+    registration VARCHAR(16) NULL,
+    cn VARCHAR(8) NULL,
+    tow_id BIGINT NULL,
+    launch_method CHAR(1) NULL,
+    release_ts BIGINT NULL,
+    release_alt BIGINT NULL,
+    release_agl BIGINT NULL,
+    tow_duration BIGINT NULL
+);
+
+CREATE TABLE IF NOT EXISTS permanent_storage (
+    id BIGSERIAL PRIMARY KEY,
+    addr VARCHAR(16) NOT NULL,
+    addr_type VARCHAR(4) NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_address_event_ts ON logbook_events (address, address_type, event, ts);
+CREATE INDEX IF NOT EXISTS idx_events_event_ts ON logbook_events (event, ts);
+CREATE INDEX IF NOT EXISTS idx_events_location ON logbook_events USING GIST (location);
+
+CREATE INDEX IF NOT EXISTS idx_entries_address_takeoff_ts ON logbook_entries (address, address_type, takeoff_ts);
+CREATE INDEX IF NOT EXISTS idx_entries_landing_ts ON logbook_entries (landing_ts);
+CREATE INDEX IF NOT EXISTS idx_entries_takeoff_location ON logbook_entries USING GIST (takeoff_location);
+CREATE INDEX IF NOT EXISTS idx_entries_landing_location ON logbook_entries USING GIST (landing_location);
+
+CREATE INDEX IF NOT EXISTS idx_permanent_storage_addr_type ON permanent_storage (addr_type, active);
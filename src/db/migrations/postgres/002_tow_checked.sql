@@ -0,0 +1,6 @@
+-- Gliders and tow planes the TowLookup has looked for their counterpart already (paired or not).
+-- The entries before this migration have been looked at by the time-window based lookup.
+
+ALTER TABLE logbook_entries ADD COLUMN IF NOT EXISTS tow_checked BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE logbook_entries SET tow_checked = TRUE;
@@ -0,0 +1,74 @@
+-- Logbook and positions of a single-file deployment.
+
+CREATE TABLE IF NOT EXISTS logbook_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ts INTEGER NOT NULL,
+    address TEXT NOT NULL,
+    address_type TEXT NOT NULL,
+    aircraft_type INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    lat REAL NOT NULL,
+    lon REAL NOT NULL,
+    location_icao TEXT NULL,
+    flight_time INTEGER NULL,
+    outlanding INTEGER NOT NULL DEFAULT 0,
+    elevation INTEGER NULL,
+    runway TEXT NULL
+);
+
+CREATE TABLE IF NOT EXISTS logbook_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address TEXT NOT NULL,
+    address_type TEXT NOT NULL,
+    aircraft_type INTEGER NOT NULL,
+    takeoff_ts INTEGER NULL,
+    takeoff_lat REAL NULL,
+    takeoff_lon REAL NULL,
+    takeoff_icao TEXT NULL,
+    landing_ts INTEGER NULL,
+    landing_lat REAL NULL,
+    landing_lon REAL NULL,
+    landing_icao TEXT NULL,
+    flight_time INTEGER NULL,
+    flown_distance INTEGER NULL,
+    max_alt INTEGER NULL,
+    device_type TEXT NULL,
+    registration TEXT NULL,
+    cn TEXT NULL,
+    tow_id INTEGER NULL,
+    launch_method TEXT NULL,
+    release_ts INTEGER NULL,
+    release_alt INTEGER NULL,
+    release_agl INTEGER NULL,
+    tow_duration INTEGER NULL
+);
+
+CREATE TABLE IF NOT EXISTS permanent_storage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    addr TEXT NOT NULL,
+    addr_type TEXT NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS positions (
+    addr TEXT NOT NULL,
+    ts INTEGER NOT NULL,
+    lat REAL NOT NULL,
+    lon REAL NOT NULL,
+    alt INTEGER NOT NULL,
+    agl INTEGER NOT NULL,
+    gs INTEGER NOT NULL,
+    vs REAL NOT NULL,
+    tr REAL NOT NULL,
+    ss INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_address_event_ts ON logbook_events (address, address_type, event, ts);
+CREATE INDEX IF NOT EXISTS idx_events_event_ts ON logbook_events (event, ts);
+
+CREATE INDEX IF NOT EXISTS idx_entries_address_takeoff_ts ON logbook_entries (address, address_type, takeoff_ts);
+CREATE INDEX IF NOT EXISTS idx_entries_landing_ts ON logbook_entries (landing_ts);
+
+CREATE INDEX IF NOT EXISTS idx_permanent_storage_addr_type ON permanent_storage (addr_type, active);
+
+CREATE INDEX IF NOT EXISTS idx_positions_addr_ts ON positions (addr, ts);
@@ -0,0 +1,134 @@
+//! Per-aircraft state (status, filtered ground speed, ..) shared by the workers and the cron jobs.
+//! Keys are prefixed by address type + address, e.g. "O123456-status".
+
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+
+use crate::clock::{Clock, SystemClock};
+use crate::configuration::{get_state_store_type, STATE_SNAPSHOT_FILEPATH};
+use crate::worker::data_structures::{AircraftStatusWithTs, ApproachInfo, PhaseCandidate};
+
+pub mod memory_state_store;
+pub mod redis_state_store;
+
+use memory_state_store::MemoryStateStore;
+use redis_state_store::RedisStateStore;
+
+pub trait StateStore: Send {
+    fn get(&mut self, key: &str) -> Option<String>;
+
+    /// @param ttl: record expiration [s]
+    fn set(&mut self, key: &str, value: &str, ttl: usize);
+
+    fn del(&mut self, key: &str);
+
+    /// @return all keys ending with given suffix
+    fn keys(&mut self, suffix: &str) -> Vec<String>;
+
+    fn get_status(&mut self, key_prefix: &str) -> Option<AircraftStatusWithTs> {
+        self.get(&format!("{key_prefix}-status")).map(|s| AircraftStatusWithTs::from_redis_str(&s))
+    }
+
+    fn set_status(&mut self, key_prefix: &str, status: &AircraftStatusWithTs, ttl: usize) {
+        self.set(&format!("{key_prefix}-status"), &status.as_redis_str(), ttl);
+    }
+
+    /// @return all known aircraft statuses as (key prefix, status)
+    fn list_statuses(&mut self) -> Vec<(String, AircraftStatusWithTs)> {
+        let mut statuses = Vec::new();
+        for key in self.keys("-status") {
+            let key_prefix = key.trim_end_matches("-status").to_string();
+            if let Some(status) = self.get_status(&key_prefix) {
+                statuses.push((key_prefix, status));
+            }
+        }
+
+        statuses
+    }
+
+    /// @return filtered ground speed [km/h]
+    fn get_gs(&mut self, key_prefix: &str) -> Option<f64> {
+        self.get(&format!("{key_prefix}-gs")).and_then(|gs| gs.parse().ok())
+    }
+
+    fn set_gs(&mut self, key_prefix: &str, gs: f64, ttl: usize) {
+        self.set(&format!("{key_prefix}-gs"), &format!("{:.0}", gs), ttl);
+    }
+
+    fn get_approach(&mut self, key_prefix: &str) -> Option<ApproachInfo> {
+        self.get(&format!("{key_prefix}-approach")).and_then(|s| ApproachInfo::from_redis_str(&s))
+    }
+
+    fn set_approach(&mut self, key_prefix: &str, approach: &ApproachInfo, ttl: usize) {
+        self.set(&format!("{key_prefix}-approach"), &approach.as_redis_str(), ttl);
+    }
+
+    fn del_approach(&mut self, key_prefix: &str) {
+        self.del(&format!("{key_prefix}-approach"));
+    }
+
+    fn get_candidate(&mut self, key_prefix: &str) -> Option<PhaseCandidate> {
+        self.get(&format!("{key_prefix}-candidate")).and_then(|s| PhaseCandidate::from_redis_str(&s))
+    }
+
+    fn set_candidate(&mut self, key_prefix: &str, candidate: &PhaseCandidate, ttl: usize) {
+        self.set(&format!("{key_prefix}-candidate"), &candidate.as_redis_str(), ttl);
+    }
+
+    fn del_candidate(&mut self, key_prefix: &str) {
+        self.del(&format!("{key_prefix}-candidate"));
+    }
+
+    /// Flags mark one-off actions (e.g. an alert has been sent already).
+    fn set_flag(&mut self, key_prefix: &str, flag: &str, ttl: usize) {
+        self.set(&format!("{key_prefix}-{flag}"), "1", ttl);
+    }
+
+    fn has_flag(&mut self, key_prefix: &str, flag: &str) -> bool {
+        self.get(&format!("{key_prefix}-{flag}")).is_some()
+    }
+
+    /// @return ts of the last position a lost-contact alert has been raised for
+    fn get_alert_ts(&mut self, key_prefix: &str) -> Option<i64> {
+        self.get(&format!("{key_prefix}-alert")).and_then(|ts| ts.parse().ok())
+    }
+
+    fn set_alert_ts(&mut self, key_prefix: &str, ts: i64, ttl: usize) {
+        self.set(&format!("{key_prefix}-alert"), &ts.to_string(), ttl);
+    }
+
+    /// Drops everything known about the aircraft.
+    fn forget(&mut self, key_prefix: &str) {
+        self.del(&format!("{key_prefix}-status"));
+        self.del(&format!("{key_prefix}-gs"));
+        self.del_approach(key_prefix);
+        self.del_candidate(key_prefix);
+    }
+}
+
+lazy_static! {
+    // one in-process store shared by all workers and cron jobs:
+    static ref MEMORY_STATE_STORE: MemoryStateStore = MemoryStateStore::new(Some(STATE_SNAPSHOT_FILEPATH.into()), Arc::new(SystemClock {}));
+    // replaces the configured store (see use_isolated_store()):
+    static ref ISOLATED_STATE_STORE: Mutex<Option<MemoryStateStore>> = Mutex::new(None);
+}
+
+/// Makes get_state_store() return a fresh in-memory store without any snapshot file from now on,
+/// e.g. a replay must not touch the state of aircraft the live instance is tracking right now.
+/// @param clock: drives the expiration of the records
+pub fn use_isolated_store(clock: Arc<dyn Clock>) {
+    *ISOLATED_STATE_STORE.lock().unwrap() = Some(MemoryStateStore::new(None, clock));
+}
+
+/// @return state store as configured by STATE_STORE ("redis" by default or "memory")
+pub fn get_state_store() -> Box<dyn StateStore> {
+    if let Some(store) = ISOLATED_STATE_STORE.lock().unwrap().as_ref() {
+        return Box::new(store.clone());
+    }
+
+    match get_state_store_type().as_str() {
+        "memory" => Box::new(MEMORY_STATE_STORE.clone()),
+        _ => Box::new(RedisStateStore::new()),
+    }
+}
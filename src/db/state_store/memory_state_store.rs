@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use log::{error, info, warn};
+use serde_json::{json, Value};
+
+use crate::clock::Clock;
+use crate::configuration::STATE_SNAPSHOT_INTERVAL;
+use crate::db::state_store::StateStore;
+
+struct Records {
+    items: HashMap<String, (String, i64)>,    // key -> (value, expiration ts)
+    snapshot_filepath: Option<String>,
+    last_snapshot_ts: i64,
+}
+
+/// Keeps the state in process memory. All clones share the same records
+/// which are periodically written into a snapshot file and read back on start.
+#[derive(Clone)]
+pub struct MemoryStateStore {
+    records: Arc<Mutex<Records>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryStateStore {
+    /// @param clock: drives the record expiration and the snapshots (simulated time in replay)
+    pub fn new(snapshot_filepath: Option<String>, clock: Arc<dyn Clock>) -> MemoryStateStore {
+        let now = clock.now();
+
+        let items = match &snapshot_filepath {
+            Some(filepath) => MemoryStateStore::load_snapshot(filepath, now),
+            None => HashMap::new(),
+        };
+
+        Self {
+            records: Arc::new(Mutex::new(Records { items, snapshot_filepath, last_snapshot_ts: now })),
+            clock,
+        }
+    }
+
+    fn load_snapshot(filepath: &str, now: i64) -> HashMap<String, (String, i64)> {
+        let mut items = HashMap::new();
+
+        let content = match fs::read_to_string(filepath) {
+            Ok(content) => content,
+            Err(_) => return items, // no snapshot yet
+        };
+
+        let snapshot: Value = match serde_json::from_str(&content) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Unable to parse state snapshot '{filepath}': {e}");
+                return items;
+            }
+        };
+
+        if let Some(records) = snapshot.as_object() {
+            for (key, rec) in records {
+                let value = rec[0].as_str();
+                let expires_ts = rec[1].as_i64();
+                if let (Some(value), Some(expires_ts)) = (value, expires_ts) {
+                    if expires_ts > now {
+                        items.insert(key.clone(), (value.to_string(), expires_ts));
+                    }
+                }
+            }
+        }
+        info!("Loaded {} state records from '{filepath}'", items.len());
+
+        items
+    }
+
+    fn save_snapshot(records: &Records, filepath: &str) {
+        let snapshot: serde_json::Map<String, Value> = records.items.iter()
+            .map(|(key, (value, expires_ts))| (key.clone(), json!([value, expires_ts])))
+            .collect();
+
+        // write aside and rename to never leave a half-written snapshot behind:
+        let tmp_filepath = format!("{filepath}.tmp");
+        let res = fs::write(&tmp_filepath, Value::Object(snapshot).to_string())
+            .and_then(|_| fs::rename(&tmp_filepath, filepath));
+        if let Err(e) = res {
+            error!("Unable to write state snapshot '{filepath}': {e}");
+        }
+    }
+
+    /// Writes the snapshot immediately (e.g. on shutdown).
+    pub fn snapshot(&self) {
+        let now = self.clock.now();
+        let mut records = self.records.lock().unwrap();
+        records.items.retain(|_, (_, expires_ts)| *expires_ts > now);
+        if let Some(filepath) = records.snapshot_filepath.clone() {
+            MemoryStateStore::save_snapshot(&records, &filepath);
+        }
+    }
+
+    fn snapshot_if_due(&self, now: i64) {
+        let last_snapshot_ts = self.records.lock().unwrap().last_snapshot_ts;
+        if now - last_snapshot_ts >= STATE_SNAPSHOT_INTERVAL {
+            self.records.lock().unwrap().last_snapshot_ts = now;
+            self.snapshot();
+        }
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn get(&mut self, key: &str) -> Option<String> {
+        let now = self.clock.now();
+        let records = self.records.lock().unwrap();
+        match records.items.get(key) {
+            Some((value, expires_ts)) if *expires_ts > now => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str, ttl: usize) {
+        let now = self.clock.now();
+        self.records.lock().unwrap().items.insert(key.into(), (value.into(), now + ttl as i64));
+        self.snapshot_if_due(now);
+    }
+
+    fn del(&mut self, key: &str) {
+        self.records.lock().unwrap().items.remove(key);
+    }
+
+    fn keys(&mut self, suffix: &str) -> Vec<String> {
+        let now = self.clock.now();
+        let records = self.records.lock().unwrap();
+        records.items.iter()
+            .filter(|(key, (_, expires_ts))| key.ends_with(suffix) && *expires_ts > now)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::Arc;
+
+    use crate::clock::{Clock, SimulatedClock, SystemClock};
+    use crate::db::state_store::StateStore;
+    use crate::worker::data_structures::{AircraftStatus, AircraftStatusWithTs};
+
+    use super::MemoryStateStore;
+
+    #[test]
+    fn records_expire() {
+        let clock = Arc::new(SimulatedClock::new(1_700_000_000));
+        let mut store = MemoryStateStore::new(None, clock.clone());
+        store.set("O123456-gs", "80", 3600);
+        store.set("O123456-alert", "1", 0);
+
+        assert_eq!(store.get("O123456-gs"), Some("80".into()));
+        assert_eq!(store.get("O123456-alert"), None);
+        assert_eq!(store.keys("-alert").len(), 0);
+
+        // the expiration follows the clock, not the wall time:
+        clock.advance_to(clock.now() + 3599);
+        assert_eq!(store.get("O123456-gs"), Some("80".into()));
+        clock.advance_to(clock.now() + 1);
+        assert_eq!(store.get("O123456-gs"), None);
+        assert_eq!(store.keys("-gs").len(), 0);
+    }
+
+    #[test]
+    fn clones_share_records() {
+        let mut store = MemoryStateStore::new(None, Arc::new(SystemClock {}));
+        let mut other = store.clone();
+
+        store.set_status("F123456", &AircraftStatusWithTs::new(AircraftStatus::Airborne, 1000), 3600);
+        let statuses = other.list_statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].0, "F123456");
+        assert!(statuses[0].1.is(AircraftStatus::Airborne));
+
+        other.forget("F123456");
+        assert!(store.get_status("F123456").is_none());
+    }
+
+    #[test]
+    fn snapshot_survives_restart() {
+        let filepath = env::temp_dir().join(format!("state-snapshot-{}.json", std::process::id()));
+        let filepath = filepath.to_str().unwrap().to_string();
+
+        let mut store = MemoryStateStore::new(Some(filepath.clone()), Arc::new(SystemClock {}));
+        store.set_gs("I4B1234", 123.0, 3600);
+        store.set_flag("I4B1234", "alert", 3600);
+        store.snapshot();
+
+        let mut restarted = MemoryStateStore::new(Some(filepath.clone()), Arc::new(SystemClock {}));
+        assert_eq!(restarted.get_gs("I4B1234"), Some(123.0));
+        assert!(restarted.has_flag("I4B1234", "alert"));
+
+        let _ = std::fs::remove_file(filepath);
+    }
+}
@@ -0,0 +1,49 @@
+use log::error;
+use simple_redis::client::Client;
+
+use crate::db::redis;
+use crate::db::state_store::StateStore;
+
+pub struct RedisStateStore {
+    redis: Client,
+}
+
+impl RedisStateStore {
+    pub fn new() -> RedisStateStore {
+        Self {
+            redis: redis::get_client(),
+        }
+    }
+}
+
+impl StateStore for RedisStateStore {
+    fn get(&mut self, key: &str) -> Option<String> {
+        self.redis.get::<String>(key).ok()
+    }
+
+    fn set(&mut self, key: &str, value: &str, ttl: usize) {
+        // self.redis.set(&key, as_redis_arg!(value));  // TODO az jim to bude jednou fungovat
+        match self.redis.run_command::<String>("SET", vec![key, value]) {
+            Ok(_) => (),
+            Err(e) => { error!("upon redis set: {:?}", e); },
+        };
+        match self.redis.expire(key, ttl) {
+            Ok(_) => (),
+            Err(e) => { error!("upon redis expiration: {:?}", e); },
+        };
+    }
+
+    fn del(&mut self, key: &str) {
+        match self.redis.del(key) {
+            Ok(_) => (),
+            Err(e) => { error!("upon redis del: {:?}", e); },
+        };
+    }
+
+    fn keys(&mut self, suffix: &str) -> Vec<String> {
+        match self.redis.keys(&format!("*{suffix}")) {
+            Ok(keys) => keys,
+            Err(_) => vec![],
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Positions (tracks) of the aircraft - in InfluxDB or in the SQLite file (see configuration::get_db_type()).
+
+use chrono::{DateTime, Utc};
+use log::error;
+
+use crate::configuration::{get_db_type, get_sqlite_filepath};
+
+pub mod influx_track_store;
+pub mod sqlite_track_store;
+
+use influx_track_store::InfluxTrackStore;
+use sqlite_track_store::SqliteTrackStore;
+
+/// A received position as stored.
+#[derive(Clone)]
+pub struct Position {
+    pub time: DateTime<Utc>,
+    pub addr: String,
+    pub agl: i32,
+    pub alt: i32,
+    pub gs: u32,
+    pub lat: f64,
+    pub lon: f64,
+    pub tr: f64,
+    pub vs: f64,
+    pub ss: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackPoint {
+    pub ts: i64,    // UTC [s]
+    pub lat: f64,   // [deg]
+    pub lon: f64,   // [deg]
+    pub alt: i64,   // [m] AMSL
+    pub agl: i64,   // [m]
+    pub gs: i64,    // [km/h]
+    pub vs: f64,    // [m/s]
+}
+
+pub trait TrackStore: Send {
+    fn insert_position(&mut self, position: &Position) -> Result<(), String>;
+
+    /// Reads out positions of an aircraft between two timestamps ordered by time.
+    /// @param addr: ogn ID with prefix OGN/ICA/FLR
+    /// @return empty vector if there are no data for the aircraft
+    fn get_track(&mut self, addr: &str, start_ts: i64, end_ts: i64) -> Vec<TrackPoint>;
+
+    /// Reads out the most recent positions of an aircraft.
+    /// @param addr: ogn ID with prefix OGN/ICA/FLR
+    /// @return up to `n` positions, the latest one first
+    fn get_last_positions(&mut self, addr: &str, n: usize) -> Vec<TrackPoint>;
+}
+
+/// @return None if the store could not be opened (the error is logged)
+pub fn get_track_store() -> Option<Box<dyn TrackStore>> {
+    match get_db_type().as_str() {
+        "sqlite" => match SqliteTrackStore::new(&get_sqlite_filepath()) {
+            Ok(store) => Some(Box::new(store)),
+            Err(e) => {
+                error!("Could not open the track store: {e}");
+                None
+            }
+        },
+        _ => Some(Box::new(InfluxTrackStore::new())),
+    }
+}
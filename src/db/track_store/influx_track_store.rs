@@ -0,0 +1,62 @@
+use rinfluxdb::influxql::blocking::Client;
+use rinfluxdb::line_protocol::LineBuilder;
+use rinfluxdb::line_protocol::blocking::Client as LineClient;
+use url::Url;
+
+use crate::configuration::{INFLUX_SERIES_NAME, get_influx_url, get_influx_db_name};
+use crate::db::influxdb;
+use crate::db::track_store::{Position, TrackPoint, TrackStore};
+
+pub struct InfluxTrackStore {
+    client: Client,
+    line_client: Option<LineClient>,
+    influx_db_name: String,
+}
+
+impl InfluxTrackStore {
+    pub fn new() -> InfluxTrackStore {
+        Self {
+            client: influxdb::get_client(),
+            line_client: None,
+            influx_db_name: get_influx_db_name(),
+        }
+    }
+}
+
+impl TrackStore for InfluxTrackStore {
+    fn insert_position(&mut self, pos: &Position) -> Result<(), String> {
+        // time                addr      agl alt gs lat       lon       tr vs
+        // 1655046041000000000 OGN414931 0   504 0  49.368367 16.114133 0  0
+        let line = LineBuilder::new(INFLUX_SERIES_NAME)
+            .insert_tag("addr", format!("{}", pos.addr))
+            .insert_field("time", pos.time.timestamp_nanos())
+            .insert_field("agl", pos.agl as i64)
+            .insert_field("alt", pos.alt as i64)
+            .insert_field("gs", pos.gs as i64)
+            .insert_field("lat", pos.lat)
+            .insert_field("lon", pos.lon)
+            .insert_field("tr", pos.tr)
+            .insert_field("vs", pos.vs)
+            .insert_field("ss", pos.ss.round() as i64)
+            .build();
+
+        let line_client = self.line_client.get_or_insert_with(|| LineClient::new(Url::parse(&get_influx_url()).unwrap(), Some(("", ""))).unwrap());
+
+        // XXX line by line as the client cannot send multiple lines XXX
+        match line_client.send(&self.influx_db_name, &[line]) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.line_client = None;    // reconnect next time
+                Err(format!("upon influx send: {:?}", e))
+            },
+        }
+    }
+
+    fn get_track(&mut self, addr: &str, start_ts: i64, end_ts: i64) -> Vec<TrackPoint> {
+        influxdb::get_track(&self.client, addr, start_ts, end_ts)
+    }
+
+    fn get_last_positions(&mut self, addr: &str, n: usize) -> Vec<TrackPoint> {
+        influxdb::get_last_positions(&self.client, addr, n)
+    }
+}
@@ -0,0 +1,98 @@
+use rusqlite::{params, Connection, Row};
+
+use crate::db::logbook_repository::sqlite_logbook_repository::open_connection;
+use crate::db::track_store::{Position, TrackPoint, TrackStore};
+
+const TRACK_COLUMNS: &str = "ts, lat, lon, alt, agl, gs, vs";
+
+/// Positions in the `positions` table of the logbook SQLite file (created by the migrations).
+pub struct SqliteTrackStore {
+    conn: Connection,
+}
+
+impl SqliteTrackStore {
+    pub fn new(filepath: &str) -> Result<SqliteTrackStore, String> {
+        Ok(Self { conn: open_connection(filepath)? })
+    }
+
+    fn row_into_track_point(row: &Row) -> rusqlite::Result<TrackPoint> {
+        Ok(TrackPoint {
+            ts: row.get("ts")?,
+            lat: row.get("lat")?,
+            lon: row.get("lon")?,
+            alt: row.get("alt")?,
+            agl: row.get("agl")?,
+            gs: row.get("gs")?,
+            vs: row.get("vs")?,
+        })
+    }
+
+    fn query_track<P: rusqlite::Params>(&self, sql: &str, params: P) -> rusqlite::Result<Vec<TrackPoint>> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let track = stmt.query_map(params, SqliteTrackStore::row_into_track_point)?.collect();
+
+        track
+    }
+}
+
+impl TrackStore for SqliteTrackStore {
+    fn insert_position(&mut self, pos: &Position) -> Result<(), String> {
+        let sql = "INSERT INTO positions (addr, ts, lat, lon, alt, agl, gs, vs, tr, ss) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10);";
+
+        self.conn.prepare_cached(sql)
+            .and_then(|mut stmt| stmt.execute(params![pos.addr, pos.time.timestamp(), pos.lat, pos.lon, pos.alt, pos.agl, pos.gs, pos.vs, pos.tr, pos.ss.round() as i64]))
+            .map(|_| ())
+            .map_err(|e| format!("Error when executing '{sql}': {e}"))
+    }
+
+    fn get_track(&mut self, addr: &str, start_ts: i64, end_ts: i64) -> Vec<TrackPoint> {
+        let sql = format!("SELECT {TRACK_COLUMNS} FROM positions WHERE addr = ?1 AND ts >= ?2 AND ts <= ?3 ORDER BY ts;");
+
+        self.query_track(&sql, params![addr, start_ts, end_ts]).unwrap_or_default()
+    }
+
+    fn get_last_positions(&mut self, addr: &str, n: usize) -> Vec<TrackPoint> {
+        let sql = format!("SELECT {TRACK_COLUMNS} FROM positions WHERE addr = ?1 ORDER BY ts DESC LIMIT ?2;");
+
+        self.query_track(&sql, params![addr, n as i64]).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::db::logbook_repository::LogbookRepository;
+    use crate::db::logbook_repository::sqlite_logbook_repository::SqliteLogbookRepository;
+    use crate::db::track_store::{Position, TrackStore};
+
+    use super::SqliteTrackStore;
+
+    fn position(addr: &str, ts: i64, alt: i32) -> Position {
+        Position { time: Utc.timestamp_opt(ts, 0).unwrap(), addr: addr.into(), agl: 0, alt, gs: 80, lat: 49.0, lon: 16.0, tr: 0.0, vs: 1.5, ss: 12.3 }
+    }
+
+    #[test]
+    fn track_round_trip() {
+        let filepath = std::env::temp_dir().join(format!("ogn-logbook-track-{}.sqlite", std::process::id()));
+        let filepath = filepath.to_str().unwrap();
+        SqliteLogbookRepository::new(filepath).unwrap().migrate().unwrap();
+
+        let mut store = SqliteTrackStore::new(filepath).unwrap();
+        for (addr, ts, alt) in [("OGN123456", 1000, 500), ("OGN123456", 1010, 520), ("OGN123456", 1020, 540), ("FLRDD1234", 1010, 300)] {
+            store.insert_position(&position(addr, ts, alt)).unwrap();
+        }
+
+        let track = store.get_track("OGN123456", 1005, 1020);
+        assert_eq!(track.iter().map(|p| p.alt).collect::<Vec<i64>>(), vec![520, 540]);
+        assert_eq!(track[0].gs, 80);
+
+        let last = store.get_last_positions("OGN123456", 2);
+        assert_eq!(last.iter().map(|p| p.ts).collect::<Vec<i64>>(), vec![1020, 1010]);
+
+        assert!(store.get_track("ICA000000", 0, 2000).is_empty());
+
+        drop(store);
+        let _ = std::fs::remove_file(filepath);
+    }
+}
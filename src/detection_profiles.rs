@@ -0,0 +1,319 @@
+/**
+ * Thresholds of the take-off & landing detection per aircraft type.
+ *
+ * The built-in defaults can be tuned in a json file where each aircraft type
+ * (or "default" for all of them) overrides just the values it needs, e.g.:
+ *
+ *  {
+ *      "default": { "max_gs": 350 },
+ *      "helicopter": { "takeoff_gs": 30, "landing_gs": 10, "takeoff_min_agl": 20 },
+ *      "tow_plane": { "landing_gs": 60 },
+ *      "paraglider": { "mode": "foot_launch", "dwell_time": 45 }
+ *  }
+ *
+ * Detection modes:
+ *  "speed" - take-off and landing by ground speed thresholds (aircraft which need a runway)
+ *  "foot_launch" - take-off by sustained gain of height above the launch site, landing by sustained
+ *      near-zero AGL at low speed (paragliders and hang gliders which fly slower than a landing glider)
+ *  "hover" - as the foot_launch but the landing also requires no vertical movement; hovering low above
+ *      the ground does not last as long as the dwell time (helicopters)
+ *
+ * Gyrocopters announce themselves as powered aircraft or helicopters, they are tuned via those
+ * (e.g. "powered_aircraft": { "mode": "hover" } where gyrocopters prevail).
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde_json::Value;
+
+use ogn_client::data_structures::AircraftType;
+
+use crate::configuration::DETECTION_PROFILES_FILEPATH;
+
+pub const DPR_RUN_INTERVAL: u64 = 60;   // [s] how often the profiles file is checked for modification
+
+static SLOW_CRAFTS: [AircraftType; 8] = [AircraftType::Glider, AircraftType::Helicopter, AircraftType::Parachute, AircraftType::HangGlider, AircraftType::Paraglider, AircraftType::Baloon, AircraftType::Airship, AircraftType::Uav];
+
+static FOOT_LAUNCHED_CRAFTS: [AircraftType; 2] = [AircraftType::HangGlider, AircraftType::Paraglider];
+
+static AIRCRAFT_TYPE_NAMES: [(&str, AircraftType); 13] = [
+    ("glider", AircraftType::Glider),
+    ("tow_plane", AircraftType::TowPlane),
+    ("helicopter", AircraftType::Helicopter),
+    ("parachute", AircraftType::Parachute),
+    ("hang_glider", AircraftType::HangGlider),
+    ("paraglider", AircraftType::Paraglider),
+    ("powered_aircraft", AircraftType::PoweredAircraft),
+    ("jet_aircraft", AircraftType::JetAircraft),
+    ("baloon", AircraftType::Baloon),
+    ("airship", AircraftType::Airship),
+    ("uav", AircraftType::Uav),
+    ("unknown", AircraftType::Unknown),
+    ("obstacle", AircraftType::Obstacle),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetectionMode {
+    Speed,
+    FootLaunch,
+    Hover,
+}
+
+impl DetectionMode {
+    pub fn from_str(s: &str) -> Option<DetectionMode> {
+        match s {
+            "speed" => Some(DetectionMode::Speed),
+            "foot_launch" => Some(DetectionMode::FootLaunch),
+            "hover" => Some(DetectionMode::Hover),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionProfile {
+    pub mode: DetectionMode,
+    pub takeoff_gs: f64,        // [km/h] faster than this on the ground = take-off
+    pub landing_gs: f64,        // [km/h] slower than this in the air = landing
+    pub takeoff_roll_gs: f64,   // [km/h] the moment the take-off roll started (see RealTakeoffLookup)
+    pub min_flight_time: i64,   // [s] shorter flights are ignored
+    pub takeoff_min_agl: i32,   // [m] lower "take-offs" are most likely false detections
+    pub landing_max_agl: i32,   // [m] higher "landings" are most likely false detections
+    pub max_gs: u32,            // [km/h] beacons of faster aircraft are not processed at all (airliners, jets)
+    pub dwell_time: i64,        // [s] how long a status change needs to last to be accepted (all but the speed mode)
+    pub max_vs: f64,            // [m/s] max vertical speed when standing on the ground (hover mode)
+    pub go_around_min_sink: f64,    // [m/s] low flight near an airfield sinking faster than this is an approach (go-around / low pass)
+}
+
+impl DetectionProfile {
+    fn for_aircraft_type(aircraft_type: &AircraftType) -> DetectionProfile {
+        let mut profile = DetectionProfile::default();
+        if !SLOW_CRAFTS.contains(aircraft_type) {
+            profile.landing_gs = 50_f64;    // [km/h] tow
+        }
+
+        if *aircraft_type == AircraftType::Glider {
+            profile.go_around_min_sink = -3.0;  // well below the still-air sink - scraping low in weak lift is no approach
+        }
+
+        if FOOT_LAUNCHED_CRAFTS.contains(aircraft_type) {
+            profile.mode = DetectionMode::FootLaunch;
+            profile.landing_gs = 10_f64;
+            profile.takeoff_min_agl = 30;
+            profile.landing_max_agl = 15;
+            profile.min_flight_time = 60;
+        }
+
+        if *aircraft_type == AircraftType::Helicopter {
+            profile.mode = DetectionMode::Hover;
+            profile.landing_gs = 5_f64;
+            profile.takeoff_min_agl = 20;
+            profile.landing_max_agl = 10;
+            profile.dwell_time = 60;
+        }
+
+        profile
+    }
+
+    /// Overrides the values present in the json object.
+    fn update(&mut self, json: &Value) {
+        if let Some(v) = json["mode"].as_str() {
+            match DetectionMode::from_str(v) {
+                Some(mode) => self.mode = mode,
+                None => warn!("Unknown detection mode '{v}'"),
+            }
+        }
+        if let Some(v) = json["takeoff_gs"].as_f64() { self.takeoff_gs = v; }
+        if let Some(v) = json["landing_gs"].as_f64() { self.landing_gs = v; }
+        if let Some(v) = json["takeoff_roll_gs"].as_f64() { self.takeoff_roll_gs = v; }
+        if let Some(v) = json["min_flight_time"].as_i64() { self.min_flight_time = v; }
+        if let Some(v) = json["takeoff_min_agl"].as_i64() { self.takeoff_min_agl = v as i32; }
+        if let Some(v) = json["landing_max_agl"].as_i64() { self.landing_max_agl = v as i32; }
+        if let Some(v) = json["max_gs"].as_u64() { self.max_gs = v as u32; }
+        if let Some(v) = json["dwell_time"].as_i64() { self.dwell_time = v; }
+        if let Some(v) = json["max_vs"].as_f64() { self.max_vs = v; }
+        if let Some(v) = json["go_around_min_sink"].as_f64() { self.go_around_min_sink = v; }
+    }
+}
+
+impl Default for DetectionProfile {
+    fn default() -> Self {
+        Self {
+            mode: DetectionMode::Speed,
+            takeoff_gs: 80_f64,
+            landing_gs: 20_f64,
+            takeoff_roll_gs: 40_f64,
+            min_flight_time: 120,
+            takeoff_min_agl: 50,
+            landing_max_agl: 100,
+            max_gs: 400,
+            dwell_time: 30,
+            max_vs: 0.5,
+            go_around_min_sink: -1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DetectionProfiles {
+    profiles: HashMap<AircraftType, DetectionProfile>,
+    fallback: DetectionProfile,
+}
+
+impl DetectionProfiles {
+    /// Built-in defaults updated from the json file (if there is any).
+    pub fn new(filepath: &str) -> DetectionProfiles {
+        DetectionProfiles::load(filepath).unwrap_or_else(|e| {
+            warn!("{e}");
+            DetectionProfiles::default()
+        })
+    }
+
+    /// @return built-in defaults updated from the json file; defaults if there is no file, error if it cannot be parsed
+    fn load(filepath: &str) -> Result<DetectionProfiles, String> {
+        let mut profiles = DetectionProfiles::default();
+
+        let data = match fs::read_to_string(filepath) {
+            Ok(data) => data,
+            Err(_) => {
+                info!("No detection profiles in '{filepath}', using the defaults");
+                return Ok(profiles);
+            }
+        };
+
+        let json = serde_json::from_str::<Value>(&data)
+            .map_err(|e| format!("Could not parse detection profiles from '{filepath}': {e}"))?;
+
+        info!("Reading detection profiles from '{filepath}'");
+        profiles.update(&json);
+
+        Ok(profiles)
+    }
+
+    fn update(&mut self, json: &Value) {
+        let default = &json["default"];
+        if default.is_object() {
+            self.fallback.update(default);
+            for profile in self.profiles.values_mut() {
+                profile.update(default);
+            }
+        }
+
+        for (name, aircraft_type) in AIRCRAFT_TYPE_NAMES.iter() {
+            let item = &json[*name];
+            if item.is_object() {
+                self.profiles.get_mut(aircraft_type).unwrap().update(item);
+            }
+        }
+
+        if let Some(obj) = json.as_object() {
+            for key in obj.keys() {
+                if key != "default" && !AIRCRAFT_TYPE_NAMES.iter().any(|(name, _)| name == key) {
+                    warn!("Unknown aircraft type '{key}' in detection profiles");
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, aircraft_type: &AircraftType) -> &DetectionProfile {
+        self.profiles.get(aircraft_type).unwrap_or(&self.fallback)
+    }
+}
+
+impl Default for DetectionProfiles {
+    fn default() -> Self {
+        let profiles = AIRCRAFT_TYPE_NAMES.iter()
+            .map(|(_, aircraft_type)| (aircraft_type.clone(), DetectionProfile::for_aircraft_type(aircraft_type)))
+            .collect();
+
+        Self {
+            profiles,
+            fallback: DetectionProfile::default(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref PROFILES: ProfileService = ProfileService::new(DETECTION_PROFILES_FILEPATH);
+}
+
+/// Profiles shared by the workers and the cron jobs; loaded once and reloaded when the file changes.
+struct ProfileService {
+    filepath: String,
+    current: RwLock<Arc<DetectionProfiles>>,
+    mtime: Mutex<Option<SystemTime>>,
+}
+
+impl ProfileService {
+    fn new(filepath: &str) -> ProfileService {
+        let mtime = ProfileService::modified(filepath);
+
+        ProfileService {
+            filepath: filepath.into(),
+            current: RwLock::new(Arc::new(DetectionProfiles::new(filepath))),
+            mtime: Mutex::new(mtime),
+        }
+    }
+
+    fn modified(filepath: &str) -> Option<SystemTime> {
+        fs::metadata(filepath).and_then(|m| m.modified()).ok()
+    }
+
+    /// Reloads the profiles if the file has been modified (or appeared / vanished) since the last load;
+    /// a broken file keeps the current profiles in place.
+    fn reload_if_modified(&self) {
+        let mtime = ProfileService::modified(&self.filepath);
+        if *self.mtime.lock().unwrap() == mtime {
+            return;
+        }
+        *self.mtime.lock().unwrap() = mtime;   // do not retry a broken file until it changes again
+
+        match DetectionProfiles::load(&self.filepath) {
+            Ok(profiles) => *self.current.write().unwrap() = Arc::new(profiles),
+            Err(e) => error!("Keeping the current detection profiles: {e}"),
+        }
+    }
+}
+
+/// The current detection profiles.
+pub fn profiles() -> Arc<DetectionProfiles> {
+    PROFILES.current.read().unwrap().clone()
+}
+
+/// Called periodically; touching the file is enough to get it reloaded.
+pub fn reload_if_modified() {
+    PROFILES.reload_if_modified();
+}
+
+#[cfg(test)]
+mod tests {
+    use ogn_client::data_structures::AircraftType;
+    use serde_json::json;
+
+    use super::DetectionProfiles;
+
+    #[test]
+    fn overrides_keep_the_defaults() {
+        let mut profiles = DetectionProfiles::default();
+        assert_eq!(profiles.get(&AircraftType::Glider).landing_gs, 20.0);
+        assert_eq!(profiles.get(&AircraftType::TowPlane).landing_gs, 50.0);
+
+        profiles.update(&json!({
+            "default": { "max_gs": 350 },
+            "helicopter": { "takeoff_gs": 30, "landing_gs": 10 },
+        }));
+
+        let helicopter = profiles.get(&AircraftType::Helicopter);
+        assert_eq!(helicopter.takeoff_gs, 30.0);
+        assert_eq!(helicopter.landing_gs, 10.0);
+        assert_eq!(helicopter.max_gs, 350);
+        assert_eq!(helicopter.min_flight_time, 120);
+        assert_eq!(profiles.get(&AircraftType::TowPlane).max_gs, 350);
+        assert_eq!(profiles.get(&AircraftType::TowPlane).takeoff_gs, 80.0);
+    }
+}
@@ -1,19 +1,25 @@
 #[warn(non_snake_case)]
 
 use queues::*;
+use std::env;
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use log::info;
+use log::{info, error};
 use simplelog::{ConfigBuilder, SimpleLogger};
 use time::macros::format_description;
 
 mod airfield_manager;
+mod alerts;
+mod clock;
+use clock::SystemClock;
+mod replay;
 
 mod aircraft_beacon_listener;
 use aircraft_beacon_listener::AircraftBeaconListener;
 
 mod configuration;
+mod detection_profiles;
 use configuration::{LOG_LEVEL, get_ogn_username, OGN_APRS_FILTER_LAT, OGN_APRS_FILTER_LON, OGN_APRS_FILTER_RANGE};
 
 mod mqtt;
@@ -38,6 +44,27 @@ fn main() -> std::io::Result<()> {
     
     info!("\n\n## OGN LOGBOOK ##\n");
 
+    // ogn_logbook replay <log file> [YYYY-MM-DD]
+    let args: Vec<String> = env::args().collect();
+    if args.len() >= 3 && args[1] == "replay" {
+        return replay::replay(&args[2], args.get(3));
+    }
+
+    // ogn_logbook import-airfields <output json> <source: .cup | OpenAIP .json | airfields .json> [<source> ..]
+    if args.len() >= 4 && args[1] == "import-airfields" {
+        return airfield_manager::importers::import(&args[3..], &args[2]);
+    }
+
+    // ogn_logbook migrate
+    if args.len() >= 2 && args[1] == "migrate" {
+        return db::migrations::migrate().map(|_| ()).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+    }
+
+    if let Err(e) = db::migrations::migrate() {
+        error!("Could not migrate the db schema: {e}");
+        return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+    }
+
     let client = Arc::new(Mutex::new(OgnClient::new(&get_ogn_username())?));
     client.lock().unwrap().set_aprs_filter(OGN_APRS_FILTER_LAT, OGN_APRS_FILTER_LON, OGN_APRS_FILTER_RANGE);
     client.lock().unwrap().connect();
@@ -54,23 +81,25 @@ fn main() -> std::io::Result<()> {
         Arc::clone(&queue_safesky));
     client.lock().unwrap().set_beacon_listener(abl);
 
+    let clock = Arc::new(SystemClock {});
+
     let mut workers = vec![];
     // create and run workers:
-    let mut ogn_worker = Worker::new(AddressType::Ogn, queue_ogn);
+    let mut ogn_worker = Worker::new(AddressType::Ogn, queue_ogn, clock.clone());
     ogn_worker.start();
     workers.push(ogn_worker);
-    let mut icao_worker = Worker::new(AddressType::Icao, queue_icao);
+    let mut icao_worker = Worker::new(AddressType::Icao, queue_icao, clock.clone());
     icao_worker.start();
     workers.push(icao_worker);
-    let mut flarm_worker = Worker::new(AddressType::Flarm, queue_flarm);
+    let mut flarm_worker = Worker::new(AddressType::Flarm, queue_flarm, clock.clone());
     flarm_worker.start();
     workers.push(flarm_worker);
-    let mut safesky_worker = Worker::new(AddressType::SafeSky, queue_safesky);
+    let mut safesky_worker = Worker::new(AddressType::SafeSky, queue_safesky, clock.clone());
     safesky_worker.start();
     workers.push(safesky_worker);
 
     // create and run cron jobs:
-    let mut cron = CronJobs::new();
+    let mut cron = CronJobs::new(clock.clone());
     cron.start();
 
     // configure the ctrl+c hook:
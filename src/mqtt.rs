@@ -1,6 +1,17 @@
 use rumqttc::{MqttOptions, Client, QoS, Connection};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+use log::debug;
+
+// cleared for good by disable_publishing():
+static PUBLISHING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns all Mqtt instances into no-ops from now on, e.g. a replay must never reach the retrieve crews.
+pub fn disable_publishing() {
+    PUBLISHING_ENABLED.store(false, Ordering::Relaxed);
+}
+
 pub struct MqttMessage {
     pub topic: String,
     pub payload: String,
@@ -30,6 +41,11 @@ impl Mqtt {
     }
     
     pub fn send_mqtt_messages(&mut self, messages: &Vec<MqttMessage>) {
+        if !PUBLISHING_ENABLED.load(Ordering::Relaxed) {
+            debug!("MQTT publishing disabled, dropped {} messages", messages.len());
+            return;
+        }
+
         let mut mqttoptions = MqttOptions::new(&self.id, &self.host, self.port);
         mqttoptions.set_keep_alive(Duration::from_secs(10));
         mqttoptions.set_credentials(&self.username, &self.password);
@@ -0,0 +1,280 @@
+//! Offline replay of recorded traffic. Reads an APRS-IS text log or a JSON-lines dump of aircraft beacons
+//! and feeds it through the usual listener -> worker -> beacon processor pipeline. The workers run on
+//! a simulated clock driven by the beacon timestamps so the recorded day is processed as if it was live.
+//!
+//! The aircraft state is kept in a private in-memory store, never in the configured one (Redis),
+//! and nothing is published on MQTT.
+//! Logbook events and positions DO go to the configured databases - point DB_TYPE / SQLITE_FILE
+//! (or the DB_* and INFLUX_* variables) at a scratch database unless the replayed day is meant to be stored.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use queues::*;
+use regex::Regex;
+use serde_json::Value;
+
+use ogn_client::data_structures::{AircraftBeacon, AircraftType, AddressType, Observer};
+
+use crate::aircraft_beacon_listener::AircraftBeaconListener;
+use crate::clock::{Clock, SimulatedClock};
+use crate::configuration::get_db_type;
+use crate::db::state_store;
+use crate::mqtt;
+use crate::worker::Worker;
+
+const ADDRESS_TYPES: [AddressType; 4] = [AddressType::Ogn, AddressType::Icao, AddressType::Flarm, AddressType::SafeSky];
+
+lazy_static! {
+    // FLRDD02AE>OGFLR,qAS,AIRSQ04:/203151h4812.72N/01137.10EX091/076/A=002130 !W22! id0EDD02AE -355fpm -1.1rot 3.0dB 2e +3.5kHz gps2x4
+    static ref APRS_RE: Regex = Regex::new(
+        r"([A-Z]{3})([0-9A-F]{6})>[^:]+:/(\d{2})(\d{2})(\d{2})h(\d{2})(\d{2}\.\d{2})([NS]).(\d{3})(\d{2}\.\d{2})([EW]).(\d{3})/(\d{3})/A=(-?\d+)(?: !W(\d)(\d)!)? id([0-9A-F]{2})[0-9A-F]{6} ([+-]\d+)fpm(?: ([+-][.0-9]+)rot)?(?:.*? ([.0-9]+)dB)?"
+    ).unwrap();
+}
+
+/// Turns timestamps of the log (time of the day only) into UTC timestamps.
+struct DayTracker {
+    date: NaiveDate,
+    last_ts: i64,
+}
+
+impl DayTracker {
+    fn new(date: NaiveDate) -> DayTracker {
+        Self { date, last_ts: 0 }
+    }
+
+    /// @return UTC [s]
+    fn ts(&mut self, time: NaiveTime) -> i64 {
+        let mut ts = self.date.and_time(time).and_utc().timestamp();
+        if ts < self.last_ts - 12 * 3600 {  // passed the midnight
+            self.date = self.date.succ_opt().unwrap();
+            ts = self.date.and_time(time).and_utc().timestamp();
+        }
+        self.last_ts = self.last_ts.max(ts);
+
+        ts
+    }
+}
+
+fn parse_address_type(prefix: &str) -> Option<AddressType> {
+    ADDRESS_TYPES.iter().find(|at| at.as_long_str() == prefix || at.as_short_str() == prefix).cloned()
+}
+
+fn parse_aprs_line(line: &str, day: &mut DayTracker) -> Option<AircraftBeacon> {
+    let caps = APRS_RE.captures(line)?;
+    let num = |i: usize| caps.get(i).map(|m| m.as_str().parse::<f64>().unwrap_or(0_f64)).unwrap_or(0_f64);
+
+    let addr_type = parse_address_type(&caps[1])?;
+    let time = NaiveTime::from_hms_opt(num(3) as u32, num(4) as u32, num(5) as u32)?;
+
+    // the !Wxy! extension adds the third decimal digit of the minutes:
+    let mut lat = num(6) + (num(7) + num(15) / 1000.0) / 60.0;
+    if &caps[8] == "S" { lat = -lat; }
+    let mut lon = num(9) + (num(10) + num(16) / 1000.0) / 60.0;
+    if &caps[11] == "W" { lon = -lon; }
+
+    let id_byte = u8::from_str_radix(&caps[17], 16).ok()?;   // STttttaa
+
+    Some(AircraftBeacon {
+        ts: day.ts(time) as _,
+        addr: caps[2].to_string(),
+        addr_type,
+        aircraft_type: AircraftType::from((id_byte >> 2) & 0x0F),
+        lat,
+        lon,
+        altitude: (num(14) * 0.3048).round() as _,   // [ft] -> [m]
+        speed: (num(13) * 1.852).round() as _,        // [kt] -> [km/h]
+        climb_rate: num(18) * 0.00508,              // [fpm] -> [m/s]
+        turn_rate: num(19),
+        signal_strength: num(20),
+        ..Default::default()
+    })
+}
+
+fn parse_json_line(line: &str) -> Option<AircraftBeacon> {
+    let rec: Value = serde_json::from_str(line).ok()?;
+
+    Some(AircraftBeacon {
+        ts: rec["ts"].as_i64()? as _,
+        addr: rec["addr"].as_str()?.to_string(),
+        addr_type: parse_address_type(rec["addr_type"].as_str()?)?,
+        aircraft_type: AircraftType::from(rec["aircraft_type"].as_u64().unwrap_or(0) as u8),
+        lat: rec["lat"].as_f64()?,
+        lon: rec["lon"].as_f64()?,
+        altitude: rec["altitude"].as_i64().unwrap_or(0) as _,
+        speed: rec["speed"].as_u64().unwrap_or(0) as _,
+        climb_rate: rec["climb_rate"].as_f64().unwrap_or(0_f64),
+        turn_rate: rec["turn_rate"].as_f64().unwrap_or(0_f64),
+        signal_strength: rec["signal_strength"].as_f64().unwrap_or(0_f64),
+        ..Default::default()
+    })
+}
+
+fn wait_for_queues(queues: &Vec<Arc<Mutex<Queue<AircraftBeacon>>>>) {
+    while queues.iter().any(|q| q.lock().unwrap().size() > 0) {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Replays a recorded log.
+/// @param filepath: APRS-IS text log or JSON-lines beacon dump
+/// @param date: day the APRS log was recorded on (the APRS timestamps carry the time of the day only); today if not given
+pub fn replay(filepath: &str, date: Option<&String>) -> std::io::Result<()> {
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Date in format YYYY-MM-DD expected, got '{date}': {e}")))?,
+        None => Utc::now().date_naive(),
+    };
+
+    warn!("Replaying into the configured '{}' logbook and track store!", get_db_type());
+    info!("Replaying '{filepath}' recorded on {date}");
+
+    let queue_ogn: Arc<Mutex<Queue<AircraftBeacon>>> = Arc::new(Mutex::new(Queue::new()));
+    let queue_icao: Arc<Mutex<Queue<AircraftBeacon>>> = Arc::new(Mutex::new(Queue::new()));
+    let queue_flarm: Arc<Mutex<Queue<AircraftBeacon>>> = Arc::new(Mutex::new(Queue::new()));
+    let queue_safesky: Arc<Mutex<Queue<AircraftBeacon>>> = Arc::new(Mutex::new(Queue::new()));
+    let queues = vec![Arc::clone(&queue_ogn), Arc::clone(&queue_icao), Arc::clone(&queue_flarm), Arc::clone(&queue_safesky)];
+
+    let mut abl = AircraftBeaconListener::new(
+        Arc::clone(&queue_ogn),
+        Arc::clone(&queue_icao),
+        Arc::clone(&queue_flarm),
+        Arc::clone(&queue_safesky));
+
+    let clock = Arc::new(SimulatedClock::new(0));
+
+    // keep off the state of the live instance and the retrieve crews:
+    state_store::use_isolated_store(clock.clone());
+    mqtt::disable_publishing();
+
+    let mut workers = vec![];
+    for (addr_type, queue) in ADDRESS_TYPES.iter().zip(queues.iter()) {
+        let mut worker = Worker::new(addr_type.clone(), Arc::clone(queue), clock.clone());
+        worker.start();
+        workers.push(worker);
+    }
+
+    let mut day = DayTracker::new(date);
+    let mut num_beacons = 0;
+    let mut num_skipped = 0;
+    for line in BufReader::new(File::open(filepath)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        let beacon = if line.starts_with('{') { parse_json_line(line) } else { parse_aprs_line(line, &mut day) };
+        let beacon = match beacon {
+            Some(beacon) => beacon,
+            None => {
+                num_skipped += 1;
+                continue;
+            }
+        };
+
+        // let the workers catch up before the time moves on:
+        let ts = beacon.ts as i64;
+        if ts > clock.now() {
+            wait_for_queues(&queues);
+            clock.advance_to(ts);
+        }
+
+        abl.notify(beacon);
+        num_beacons += 1;
+    }
+
+    wait_for_queues(&queues);
+    for w in workers.iter_mut() { w.stop(); }  // writes out the queued positions & events
+
+    info!("Replayed {num_beacons} beacons, skipped {num_skipped} lines.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+
+    use ogn_client::data_structures::AircraftType;
+
+    use super::{parse_aprs_line, parse_json_line, DayTracker};
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 15).unwrap()
+    }
+
+    fn ts(date: NaiveDate, h: u32, m: u32, s: u32) -> i64 {
+        date.and_hms_opt(h, m, s).unwrap().and_utc().timestamp()
+    }
+
+    #[test]
+    fn aprs_lines() {
+        let mut day = DayTracker::new(date());
+
+        let line = "FLRDD02AE>OGFLR,qAS,AIRSQ04:/203151h4812.72N/01137.10EX091/076/A=002130 !W22! id0EDD02AE -355fpm -1.1rot 3.0dB 2e +3.5kHz gps2x4";
+        let beacon = parse_aprs_line(line, &mut day).unwrap();
+        assert_eq!(beacon.ts as i64, ts(date(), 20, 31, 51));
+        assert_eq!(beacon.addr, "DD02AE");
+        assert_eq!(beacon.addr_type.as_long_str(), "FLR");
+        assert_eq!(beacon.aircraft_type, AircraftType::from(3));
+        assert!((beacon.lat - 48.212033).abs() < 1e-5);
+        assert!((beacon.lon - 11.618367).abs() < 1e-5);
+        assert_eq!(beacon.altitude as i64, 649);
+        assert_eq!(beacon.speed as i64, 141);
+        assert!((beacon.climb_rate - -1.8034).abs() < 1e-3);
+        assert!((beacon.turn_rate - -1.1).abs() < 1e-9);
+        assert!((beacon.signal_strength - 3.0).abs() < 1e-9);
+
+        // southern & western hemisphere, no !W! precision extension, no signal strength:
+        let line = "ICA4B4E5C>OGADSB,qAS,SpainAVX:/202012h3342.15S/07035.44W^270/120/A=003500 id254B4E5C +640fpm +0.0rot";
+        let beacon = parse_aprs_line(line, &mut day).unwrap();
+        assert_eq!(beacon.addr_type.as_long_str(), "ICA");
+        assert!((beacon.lat - -33.7025).abs() < 1e-5);
+        assert!((beacon.lon - -70.590667).abs() < 1e-5);
+        assert!((beacon.climb_rate - 3.2512).abs() < 1e-3);
+        assert_eq!(beacon.signal_strength, 0.0);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let mut day = DayTracker::new(date());
+
+        assert!(parse_aprs_line("OGN123456>OGNFNT,qAS,LKKA:>203151h status message", &mut day).is_none());
+        assert!(parse_aprs_line("XYZDD02AE>OGFLR,qAS,AIRSQ04:/203151h4812.72N/01137.10EX091/076/A=002130 id0EDD02AE -355fpm", &mut day).is_none());
+        assert!(parse_aprs_line("FLRDD02AE>OGFLR,qAS,AIRSQ04:/256151h4812.72N/01137.10EX091/076/A=002130 id0EDD02AE -355fpm", &mut day).is_none());
+        assert!(parse_aprs_line("garbage", &mut day).is_none());
+
+        assert!(parse_json_line("{\"ts\": 1718483511, \"addr\": \"DD02AE\", \"addr_type\": \"F\"}").is_none());    // no position
+        assert!(parse_json_line("{\"ts\": 1718483511, \"addr\": \"DD02AE\",").is_none());
+    }
+
+    #[test]
+    fn json_line() {
+        let line = r#"{"ts": 1718483511, "addr": "DD02AE", "addr_type": "FLR", "aircraft_type": 1, "lat": 48.212, "lon": 11.6184, "altitude": 649, "speed": 141, "climb_rate": -1.8}"#;
+        let beacon = parse_json_line(line).unwrap();
+        assert_eq!(beacon.ts as i64, 1718483511);
+        assert_eq!(beacon.addr, "DD02AE");
+        assert_eq!(beacon.addr_type.as_long_str(), "FLR");
+        assert_eq!(beacon.aircraft_type, AircraftType::Glider);
+        assert_eq!(beacon.lat, 48.212);
+        assert_eq!(beacon.speed as i64, 141);
+        assert_eq!(beacon.turn_rate, 0.0);
+    }
+
+    #[test]
+    fn midnight_rollover() {
+        let mut day = DayTracker::new(date());
+        let next_date = date().succ_opt().unwrap();
+
+        assert_eq!(day.ts(NaiveTime::from_hms_opt(23, 59, 50).unwrap()), ts(date(), 23, 59, 50));
+        // slightly out of order stays on the same day:
+        assert_eq!(day.ts(NaiveTime::from_hms_opt(23, 59, 40).unwrap()), ts(date(), 23, 59, 40));
+        assert_eq!(day.ts(NaiveTime::from_hms_opt(0, 0, 10).unwrap()), ts(next_date, 0, 0, 10));
+        // the following beacons stay on the new day:
+        assert_eq!(day.ts(NaiveTime::from_hms_opt(0, 5, 0).unwrap()), ts(next_date, 0, 5, 0));
+    }
+}
@@ -11,15 +11,19 @@ use queues::*;
 
 use ogn_client::data_structures::{AircraftBeacon, AddressType};
 
+use crate::clock::Clock;
+
 mod beacon_processor;
 use beacon_processor::BeaconProcessor;
-mod data_structures;
+pub(crate) mod data_structures;
 mod db_thread;
 mod expiring_dict;
-mod geo_file;
-mod influx_worker;
+pub(crate) mod flight_phase_detector;
+pub(crate) mod geo_file;
+pub(crate) mod terrain;
+mod mqtt_worker;
 mod permanent_storage;
-mod utils;
+pub(crate) mod position_worker;
 mod python_influx_bridge;
 
 pub struct Worker {
@@ -27,18 +31,21 @@ pub struct Worker {
     do_run: Arc<AtomicBool>,
     worker_type: AddressType,
     queue: Arc<Mutex<Queue<AircraftBeacon>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Worker {
-    pub fn new(worker_type: AddressType,  queue: Arc<Mutex<Queue<AircraftBeacon>>>) -> Worker {
+    pub fn new(worker_type: AddressType,  queue: Arc<Mutex<Queue<AircraftBeacon>>>, clock: Arc<dyn Clock>) -> Worker {
         Self {
             thread: None, //Some(thread),
             do_run: Arc::new(AtomicBool::new(true)),
             worker_type,
             queue: queue,
+            clock,
         }
     }
 
+    /// Stops the worker once the positions and events of the processed beacons have been written.
     pub fn stop(&mut self) {
         info!("Stopping worker for {}", self.worker_type.as_long_str());
         self.do_run.swap(false, Ordering::Relaxed);
@@ -58,11 +65,11 @@ impl Worker {
         let do_run = Arc::clone(&self.do_run);
         let worker_name = self.worker_type.as_long_str();
         let worker_type = self.worker_type.clone();
+        let clock = Arc::clone(&self.clock);
 
         let thread = thread::Builder::new().name(self.worker_type.as_long_str()).spawn(
             move || {
-                // let mut geo_file = GeoFile::new(GEOTIFF_FILEPATH);
-                let mut bp = BeaconProcessor::new(&worker_type);
+                let mut bp = BeaconProcessor::new(&worker_type, clock);
 
                 while do_run.load(Ordering::Relaxed) {
                     let num_queued = q.lock().unwrap().size();
@@ -89,6 +96,7 @@ impl Worker {
                     }
                 }
 
+                bp.stop();
                 info!("{} worker thread terminated.", worker_name);
         }).unwrap();
 
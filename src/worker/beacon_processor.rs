@@ -3,21 +3,24 @@ use std::sync::Arc;
 
 use chrono::prelude::*;
 use lazy_static::lazy_static;
-use log::{debug, info, error};
-use simple_redis::client::Client;
-use simple_redis::RedisResult;
+use log::{debug, info};
 
 use ogn_client::data_structures::{AircraftBeacon, AircraftType, AddressType};
 
-use crate::configuration::{GEOTIFF_FILEPATH, REDIS_RECORD_EXPIRATION, AIRFIELDS_FILEPATH, get_db_url, AGL_LANDING_LIMIT, get_influx_db_name};
-use crate::airfield_manager::AirfieldManager;
-use crate::db::redis;
-use crate::worker::data_structures::{AircraftStatus, AircraftStatusWithTs};
-use crate::worker::geo_file::GeoFile;
+use crate::alerts::OutlandingAlert;
+use crate::clock::Clock;
+use crate::configuration::{DEM_DIRPATH, GEOTIFF_FILEPATH, REDIS_RECORD_EXPIRATION, debug, get_mqtt_config};
+use crate::airfield_manager::airfield_service;
+use crate::detection_profiles::{self, DetectionMode, DetectionProfiles};
+use crate::db::data_structures::LogbookEvent;
+use crate::db::state_store::{self, StateStore};
+use crate::mqtt::Mqtt;
 use crate::worker::db_thread::DbThread;
 use crate::worker::expiring_dict::ExpiringDict;
-use crate::worker::utils::get_groundspeed_threshold;
-use crate::worker::influx_worker::InfluxWorker;
+use crate::worker::flight_phase_detector::{BeaconSample, FlightPhaseDetector, FlightPhaseEvent, FlightState};
+use crate::worker::mqtt_worker::MqttWorker;
+use crate::worker::position_worker::PositionWorker;
+use crate::worker::terrain::Terrain;
 // use crate::worker::permanent_storage::PermanentStorageFactory;
 
 use super::permanent_storage::PermanentStorage;
@@ -26,44 +29,64 @@ use super::permanent_storage::PermanentStorage;
 // static UNSUPPORTED_CRAFTS: [AircraftType; 6] = [AircraftType::Undefined, AircraftType::Unknown, AircraftType::Baloon, AircraftType::Airship, AircraftType::Uav, AircraftType::Reserved];
 
 pub struct BeaconProcessor {
-    geo_file: GeoFile,
-    redis: Client,
-    airfield_manager: AirfieldManager,
+    terrain: Terrain,
+    state_store: Box<dyn StateStore>,
+    detection_profiles: Arc<DetectionProfiles>,
+    flight_phase_detector: FlightPhaseDetector,
     db_thread: DbThread,
     beacon_duplicate_cache:ExpiringDict<String, bool>,
-    influx_worker: InfluxWorker,
+    position_worker: PositionWorker,
     // influx_worker_ps: InfluxWorker,
+    mqtt_worker: MqttWorker,
+    clock: Arc<dyn Clock>,
     t: i64,
     // permanent_storage: Arc<PermanentStorage>,
 }
 
 impl BeaconProcessor {
 
-    pub fn new(addr_type: &AddressType) -> BeaconProcessor {
-        let mut db_thread = DbThread::new(&get_db_url());
+    pub fn new(addr_type: &AddressType, clock: Arc<dyn Clock>) -> BeaconProcessor {
+        let mut db_thread = DbThread::new();
         db_thread.start();
 
-        let mut influx_worker = InfluxWorker::new(get_influx_db_name().into());
-        influx_worker.start();
+        let mut position_worker = PositionWorker::new();
+        position_worker.start();
 
         // let mut influx_worker_ps = InfluxWorker::new(get_influx_db_name()+"_ps");   // permanent storage
         // influx_worker_ps.start();
 
+        let (mqtt_id, mqtt_host, mqtt_port, mqtt_username, mqtt_password) = get_mqtt_config();
+        let mqtt = Mqtt::new(&format!("{mqtt_id}-{}", addr_type.as_short_str()), &mqtt_host, mqtt_port, &mqtt_username, &mqtt_password);
+        let mut mqtt_worker = MqttWorker::new(mqtt);
+        mqtt_worker.start();
+
+        let detection_profiles = detection_profiles::profiles();
+
         BeaconProcessor { 
-            geo_file: GeoFile::new(GEOTIFF_FILEPATH), 
-            redis: redis::get_client(),
-            airfield_manager: AirfieldManager::new(AIRFIELDS_FILEPATH),
+            terrain: Terrain::new(DEM_DIRPATH, GEOTIFF_FILEPATH),
+            state_store: state_store::get_state_store(),
+            flight_phase_detector: FlightPhaseDetector::new(Arc::clone(&detection_profiles)),
+            detection_profiles,
             db_thread: db_thread,
-            beacon_duplicate_cache: ExpiringDict::new(1000),
-            influx_worker,
+            beacon_duplicate_cache: ExpiringDict::new(1000, Arc::clone(&clock)),
+            position_worker,
             // influx_worker_ps,
+            mqtt_worker,
+            clock,
             t: 0,
             // permanent_storage: PermanentStorageFactory::instance().storage_for(&addr_type),
         }
     }
 
+    /// Writes out the queued positions, logbook events and alerts and stops the background threads.
+    pub fn stop(&mut self) {
+        self.position_worker.stop();
+        self.db_thread.stop();
+        self.mqtt_worker.stop();
+    }
+
     fn get_agl(&mut self, beacon: &AircraftBeacon) -> Option<i32> {
-        let terrain_elevation = self.geo_file.get_value(beacon.lat, beacon.lon);
+        let terrain_elevation = self.terrain.get_value(beacon.lat, beacon.lon);
 
         match terrain_elevation {
             Some(e) => {
@@ -77,33 +100,6 @@ impl BeaconProcessor {
         }
     }
 
-    fn get_from_redis(&mut self, key: &String) -> RedisResult<String> {
-        // match self.redis.get::<String>(&key) {
-        //     Ok(val) => val,
-        //     Err(_) => "".to_string()
-        // }
-        self.redis.get::<String>(&key)
-    }
-
-    fn save_to_redis(&mut self, key: &String, value: &String, expiration: usize) {
-        // self.redis.set(&key, as_redis_arg!(value));  // TODO az jim to bude jednou fungovat
-        match self.redis.run_command::<String>("SET", vec![&key, &value]) {
-            Ok(_) => (),
-            Err(e) => { error!("upon redis set: {:?}", e); },
-        };
-        match self.redis.expire(&key, expiration) {
-            Ok(_) => (),
-            Err(e) => { error!("upon redis expiration: {:?}", e); },
-        };
-    }
-
-    fn del_in_redis(&mut self, key: &str) {
-        match self.redis.del(key) {
-            Ok(_) => (),
-            Err(e) => { error!("upon redis del: {:?}", e); },
-        };
-    }
-
     fn xstart(&mut self, addr_type: &AddressType) {
         if AddressType::Icao.eq(addr_type) {
             self.t = Utc::now().timestamp_micros();
@@ -121,6 +117,106 @@ impl BeaconProcessor {
         }
     }
 
+    fn load_flight_state(&mut self, key_prefix: &str, ts: i64) -> FlightState {
+        let status = match self.state_store.get_status(key_prefix) {
+            Some(status) => status,
+            None => return FlightState::unknown(ts),
+        };
+
+        let gs = self.state_store.get_gs(key_prefix).unwrap_or(0_f64);
+        let approach = self.state_store.get_approach(key_prefix);
+
+        let mut state = FlightState::new(status, gs, approach);
+        state.candidate = self.state_store.get_candidate(key_prefix);
+
+        state
+    }
+
+    fn save_flight_state(&mut self, key_prefix: &str, prior: &FlightState, state: &Option<FlightState>) {
+        let state = match state {
+            Some(state) => state,
+            None => {
+                self.state_store.forget(key_prefix);
+                return;
+            },
+        };
+
+        if state.status.status != prior.status.status || state.status.ts != prior.status.ts {
+            self.state_store.set_status(key_prefix, &state.status, REDIS_RECORD_EXPIRATION);
+        }
+
+        if state.gs > 0_f64 {
+            self.state_store.set_gs(key_prefix, state.gs, 3600);
+        }
+
+        match &state.approach {
+            Some(approach) => self.state_store.set_approach(key_prefix, approach, REDIS_RECORD_EXPIRATION),
+            None => if prior.approach.is_some() { self.state_store.del_approach(key_prefix) },
+        }
+
+        match &state.candidate {
+            Some(candidate) => if prior.candidate != state.candidate { self.state_store.set_candidate(key_prefix, candidate, REDIS_RECORD_EXPIRATION) },
+            None => if prior.candidate.is_some() { self.state_store.del_candidate(key_prefix) },
+        }
+    }
+
+    fn store_event(&mut self, beacon: &AircraftBeacon, flight_phase_event: &FlightPhaseEvent) {
+        let addres_type_c = beacon.addr_type.as_short_str();
+        let address = &beacon.addr;
+
+        let event = flight_phase_event.event;
+        let ts = flight_phase_event.ts;
+        let lat = flight_phase_event.lat;
+        let lon = flight_phase_event.lon;
+        let icao_location = flight_phase_event.icao_location.clone();
+        let flight_time = flight_phase_event.flight_time;
+
+        let naive = NaiveDateTime::from_timestamp_opt(ts, 0).unwrap();
+        let dt_str = DateTime::<Utc>::from_utc(naive, Utc).format("%H:%M:%S");
+        let icao_location_str = if icao_location.is_some() {icao_location.clone().unwrap()} else {"?".into()};
+        let flight_time_str = if flight_time > 0 { format!("{flight_time}s") } else { "".into() };
+        if icao_location_str == "?" {
+            info!("EVENT: {dt_str}; loc: {icao_location_str} [{addres_type_c}] {} {event} {flight_time_str} | {:.5} {:.5}", beacon.addr, lat, lon);
+        } else {
+            info!("EVENT: {dt_str}; loc: {icao_location_str} [{addres_type_c}] {} {event} {flight_time_str}", beacon.addr);
+        }
+
+        // a landing away from any known airfield:
+        let outlanding = event == 'L' && icao_location.is_none();
+        let elevation = if outlanding { self.terrain.get_value(lat, lon) } else { None };
+        let speed_mode = self.detection_profiles.get(&beacon.aircraft_type).mode == DetectionMode::Speed;
+        if outlanding && speed_mode {  // landing in a field is the daily routine of foot-launched aircraft and helicopters
+            let alert = OutlandingAlert {
+                ts,
+                address: address.clone(),
+                address_type: beacon.addr_type.clone(),
+                aircraft_type: beacon.aircraft_type.clone(),
+                lat,
+                lon,
+                elevation,
+            };
+            info!("OUTLANDING: [{addres_type_c}] {} at {:.5} {:.5}", beacon.addr, lat, lon);
+            if !debug() {
+                self.mqtt_worker.send(alert.as_mqtt_message());
+            }
+        }
+
+        self.db_thread.add_event(LogbookEvent {
+            id: 0,
+            ts,
+            event: event.to_string(),
+            address: address.clone(),
+            address_type: beacon.addr_type.clone(),
+            aircraft_type: beacon.aircraft_type.clone(),
+            lat,
+            lon,
+            location_icao: icao_location.unwrap_or_default(),
+            flight_time,
+            outlanding,
+            elevation,
+        });
+    }
+
     pub fn process(&mut self, beacon: &mut AircraftBeacon) {
         lazy_static! {
             static ref UNSUPPORTED_CRAFTS: HashSet<AircraftType> = 
@@ -136,7 +232,7 @@ impl BeaconProcessor {
 
         // println!("beacon: {beacon}");
         let ts = beacon.ts as i64; // UTC [s]
-        let now = chrono::offset::Utc::now().timestamp();
+        let now = self.clock.now();
         if ts - now > 120 {
             debug!("Timestamp from the future for {}: {ts}, now is {now} ({}s)", &beacon.addr, ts-now);
             return;
@@ -150,8 +246,8 @@ impl BeaconProcessor {
         }
         self.xstop(&beacon.addr_type,"U1");
 
-        // store the beacon into influxdb:
-        self.influx_worker.store(&beacon);
+        // store the position (influxdb / sqlite):
+        self.position_worker.store(&beacon, agl);
         // if self.permanent_storage.eligible4ps(&beacon.addr) {
         //     self.influx_worker_ps.store(&beacon);
         // } else {
@@ -170,114 +266,49 @@ impl BeaconProcessor {
             self.beacon_duplicate_cache.insert(key, true);  // store a marker in the cache .. will be dropped after TTL automatically later
         };
 
-        if beacon.speed > 400 { // ignore fast (icao) airliners and jets
+        // pick up reloaded profiles:
+        let detection_profiles = detection_profiles::profiles();
+        if !Arc::ptr_eq(&detection_profiles, &self.detection_profiles) {
+            self.flight_phase_detector.set_profiles(Arc::clone(&detection_profiles));
+            self.detection_profiles = detection_profiles;
+        }
+
+        if beacon.speed > self.detection_profiles.get(&beacon.aircraft_type).max_gs { // ignore fast (icao) airliners and jets
             return;
         }
         self.xstop(&beacon.addr_type,"U3");
 
-        let status_key = format!("{addres_type_c}{address}-status");
-        let prev_status = match self.get_from_redis(&status_key) {
-            Ok(ps) => AircraftStatusWithTs::from_redis_str(&ps),
-            Err(_) => AircraftStatusWithTs::new(AircraftStatus::Unknown, beacon.ts),
-        };
+        let key_prefix = format!("{addres_type_c}{address}");
+        let prior = self.load_flight_state(&key_prefix, beacon.ts);
         self.xstop(&beacon.addr_type,"U4");
-        
-        let mut gs = beacon.speed as f64;  // [km/h]
-        let gs_key = format!("{addres_type_c}{address}-gs");
-        if prev_status.is(AircraftStatus::Unknown) { // we have no prior information
-            // let status = format!("0;{ts}"); // 0 = AircraftStatus::OnGround
-            let status = AircraftStatusWithTs::new(AircraftStatus::OnGround, beacon.ts);
-            self.save_to_redis(&status_key, &status.as_redis_str(), REDIS_RECORD_EXPIRATION);
-            self.save_to_redis(&gs_key, &0.to_string(), 120); // gs = 0
-        }
-        self.xstop(&beacon.addr_type,"U5");
 
-        let prev_gs = match self.get_from_redis(&gs_key) {
-            Ok(gs) => gs.parse().unwrap_or(0_f64),
-            Err(_) => 0_f64
+        let sample = BeaconSample::new(beacon, agl);
+        let airfield_manager = airfield_service::airfields();
+        let sites = match self.detection_profiles.get(&beacon.aircraft_type).mode {
+            DetectionMode::FootLaunch => airfield_service::launch_sites(),
+            DetectionMode::Hover => airfield_service::heliports(),
+            DetectionMode::Speed => None,
         };
-        if prev_gs > 0_f64 { // filter speed change a bit (sometimes there are glitches in speed with badly placed gps antenna):
-            gs = gs * 0.7 + prev_gs * 0.3;
-        }
-        if gs > 0_f64 {
-            self.save_to_redis(&gs_key, &format!("{:.0}", gs.round()), 3600);
-        }
-        self.xstop(&beacon.addr_type,"U6");
-
-        let mut current_status = AircraftStatusWithTs::new(AircraftStatus::Unknown, beacon.ts);
-        if prev_status.is(AircraftStatus::OnGround) {
-            current_status.status = if gs > get_groundspeed_threshold(&beacon.aircraft_type, 'T') { AircraftStatus::Airborne } else { AircraftStatus::OnGround };
-        } else {    // when airborne
-            current_status.status = if gs <= get_groundspeed_threshold(&beacon.aircraft_type, 'L') { AircraftStatus::OnGround } else { AircraftStatus::Airborne };
-        }
+        let nearest_location = |lat, lon| {
+            sites.as_ref().and_then(|sites| sites.get_nearest(lat, lon)).or_else(|| airfield_manager.get_nearest(lat, lon))
+        };
+        let detection = self.flight_phase_detector.detect(&sample, &prior, &nearest_location);
         self.xstop(&beacon.addr_type,"U7");
 
-        if current_status.status != prev_status.status {
-            let event = if current_status.is(AircraftStatus::OnGround) {'L'} else {'T'}; // L = landing, T = take-off
-            let mut flight_time: i64 = 0;
-
-            if event == 'L' {
-                flight_time = (current_status.ts - prev_status.ts) as i64;   // [s]
-                if flight_time < 120 { return }  // [s]
-
-                if flight_time > 12 * 3600 {    // some relic from the previous day
-                    self.del_in_redis(&status_key);
-                    self.del_in_redis(&gs_key);
-                    return;
-                }
-
-                // check altitude above ground level:
-                if agl.is_some() && agl.unwrap() > AGL_LANDING_LIMIT { return };    // most likely a false detection
-
-            } else if event == 'T' {
-                // check altitude above ground level:
-                if agl.is_some() && agl.unwrap() < 50 { return }; // most likely a false detection
-            }
-            self.xstop(&beacon.addr_type,"U8");
-
-            self.save_to_redis(&status_key, &current_status.as_redis_str(), REDIS_RECORD_EXPIRATION);
-            self.xstop(&beacon.addr_type,"U9");
-
-            let icao_location = self.airfield_manager.get_nearest(beacon.lat, beacon.lon);
-            self.xstop(&beacon.addr_type,"U10");
-
-            let naive = NaiveDateTime::from_timestamp_opt(beacon.ts as i64, 0).unwrap();
-            let dt_str = DateTime::<Utc>::from_utc(naive, Utc).format("%H:%M:%S");
-            let icao_location_str = if icao_location.is_some() {icao_location.clone().unwrap()} else {"?".into()};
-            let flight_time_str = if flight_time > 0 { format!("{flight_time}s") } else { "".into() };
-            if icao_location_str == "?" {
-                info!("EVENT: {dt_str}; loc: {icao_location_str} [{addres_type_c}] {} {event} {flight_time_str} | {:.5} {:.5}", beacon.addr, beacon.lat, beacon.lon);
-            } else {
-                info!("EVENT: {dt_str}; loc: {icao_location_str} [{addres_type_c}] {} {event} {flight_time_str}", beacon.addr);
-            }
-            
-
-            let icao_location_str = match icao_location {
-                Some(loc) => format!("'{loc}'"),
-                None => "null".into()
-            };
+        self.save_flight_state(&key_prefix, &prior, &detection.state);
+        self.xstop(&beacon.addr_type,"U9");
 
-            let str_sql = format!("INSERT INTO logbook_events \
-                     (ts, address, address_type, aircraft_type, event, lat, lon, location_icao, flight_time) \
-                     VALUES \
-                     ({ts}, '{address}', '{addres_type_c}', '{0}', \
-                     '{event}', {1:.5}, {2:.5}, {icao_location_str}, {flight_time});", 
-                     beacon.aircraft_type.value(), beacon.lat, beacon.lon);
-            self.xstop(&beacon.addr_type,"U11");
-
-            // debug!("str_sql: {str_sql}");
-            self.db_thread.add_statement(str_sql);
-            self.xstop(&beacon.addr_type,"U12");
-            // panic!("BREAK!");
-
-            // TODO.. radek 289+
+        for event in detection.events.iter() {
+            self.store_event(beacon, event);
+        }
+        self.xstop(&beacon.addr_type,"U12");
 
-            // icaoLocation = icaoLocation.replace("'", '')
-            // EventWatcher.createEvent(redis=self.redis,
-            //                          ts=ts, event=event, address=address, addressType=addressType,
-            //                          lat=lat, lon=lon, icaoLocation=icaoLocation, flightTime=flightTime)
+        // TODO.. radek 289+
 
-        }
+        // icaoLocation = icaoLocation.replace("'", '')
+        // EventWatcher.createEvent(redis=self.redis,
+        //                          ts=ts, event=event, address=address, addressType=addressType,
+        //                          lat=lat, lon=lon, icaoLocation=icaoLocation, flightTime=flightTime)
 
         self.beacon_duplicate_cache.tick();    // cleanup the cache (cannot be called from PeriodicTimer due to subprocess/threading troubles :|)
 
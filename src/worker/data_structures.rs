@@ -60,3 +60,115 @@ impl AircraftStatusWithTs {
     
 }
 
+
+/// Low flight in the vicinity of an airfield - an approach which may end up 
+/// as a landing, a touch-and-go, a go-around or a low pass.
+#[derive(Debug, Clone)]
+pub struct ApproachInfo {
+    pub icao: String,
+    pub min_agl: i32,   // [m]
+    pub min_vs: f64,    // [m/s] max sink rate observed during the approach
+    pub low_ts: i64,    // lowest point of the approach (or the first ground contact)
+    pub low_lat: f64,
+    pub low_lon: f64,
+    pub low_gs: f64,    // [km/h]
+    pub touched: bool,  // ground contact detected
+}
+
+impl ApproachInfo {
+    pub fn new(icao: String, ts: i64, lat: f64, lon: f64, agl: i32, gs: f64, vs: f64) -> ApproachInfo {
+        Self {
+            icao,
+            min_agl: agl,
+            min_vs: vs,
+            low_ts: ts,
+            low_lat: lat,
+            low_lon: lon,
+            low_gs: gs,
+            touched: false,
+        }
+    }
+
+    pub fn update(&mut self, ts: i64, lat: f64, lon: f64, agl: i32, gs: f64, vs: f64) {
+        if vs < self.min_vs {
+            self.min_vs = vs;
+        }
+
+        if !self.touched && agl < self.min_agl {
+            self.min_agl = agl;
+            self.low_ts = ts;
+            self.low_lat = lat;
+            self.low_lon = lon;
+            self.low_gs = gs;
+        }
+    }
+
+    pub fn touch(&mut self, ts: i64, lat: f64, lon: f64, gs: f64) {
+        self.touched = true;
+        self.min_agl = 0;
+        self.low_ts = ts;
+        self.low_lat = lat;
+        self.low_lon = lon;
+        self.low_gs = gs;
+    }
+
+    // format: "icao;min_agl;min_vs;low_ts;low_lat;low_lon;low_gs;touched"
+    pub fn as_redis_str(&self) -> String {
+        format!("{};{};{:.1};{};{:.5};{:.5};{:.0};{}", self.icao, self.min_agl, self.min_vs, self.low_ts, self.low_lat, self.low_lon, self.low_gs, self.touched as i8)
+    }
+
+    pub fn from_redis_str(s: &str) -> Option<ApproachInfo> {
+        let items = s.split(";").collect::<Vec<&str>>();
+        if items.len() != 8 {
+            return None;
+        }
+
+        Some(ApproachInfo {
+            icao: items[0].into(),
+            min_agl: items[1].parse().ok()?,
+            min_vs: items[2].parse().ok()?,
+            low_ts: items[3].parse().ok()?,
+            low_lat: items[4].parse().ok()?,
+            low_lon: items[5].parse().ok()?,
+            low_gs: items[6].parse().ok()?,
+            touched: items[7] == "1",
+        })
+    }
+}
+
+/// Status change which has not lasted long enough to be trusted yet
+/// (e.g. a paraglider which got above the launch site AGL limit a few seconds ago).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhaseCandidate {
+    pub ts: i64,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl PhaseCandidate {
+    pub fn new(ts: i64, lat: f64, lon: f64) -> PhaseCandidate {
+        Self {
+            ts,
+            lat,
+            lon,
+        }
+    }
+
+    // format: "ts;lat;lon"
+    pub fn as_redis_str(&self) -> String {
+        format!("{};{:.5};{:.5}", self.ts, self.lat, self.lon)
+    }
+
+    pub fn from_redis_str(s: &str) -> Option<PhaseCandidate> {
+        let items = s.split(";").collect::<Vec<&str>>();
+        if items.len() != 3 {
+            return None;
+        }
+
+        Some(PhaseCandidate {
+            ts: items[0].parse().ok()?,
+            lat: items[1].parse().ok()?,
+            lon: items[2].parse().ok()?,
+        })
+    }
+}
@@ -5,29 +5,31 @@ use std::thread;
 use std::time::Duration;
 
 use log::{warn, error};
-use mysql::*;
-use mysql::prelude::*;
 
 use queues::*;
 
+use crate::db::data_structures::LogbookEvent;
+use crate::db::entry_builder;
+use crate::db::logbook_repository::{self, LogbookRepository};
+
+/// Writes the logbook events in a separate thread not to hold up the beacon processing.
 pub struct DbThread {
     thread: Option<thread::JoinHandle<()>>,
     do_run: Arc<AtomicBool>,
-    pool: Arc<Pool>,
-    to_do_statements: Arc<Mutex<Queue<String>>>,
+    to_do_events: Arc<Mutex<Queue<LogbookEvent>>>,
 }
 
 impl DbThread {
 
-    pub fn new(db_url: &str) -> DbThread {
+    pub fn new() -> DbThread {
         DbThread {
             thread: None,
             do_run: Arc::new(AtomicBool::new(true)),
-            pool: Arc::new(Pool::new(db_url).expect("Pool: Could not connect to MySQL db!")),
-            to_do_statements: Arc::new(Mutex::new(Queue::new())),
+            to_do_events: Arc::new(Mutex::new(Queue::new())),
         }
     }
 
+    /// Stops the thread once the queued events have been written.
     pub fn stop(&mut self) {
         self.do_run.swap(false, Ordering::Relaxed);
         if let Some(thread) = self.thread.take() {
@@ -35,8 +37,47 @@ impl DbThread {
         }
     }
 
-    pub fn add_statement(&mut self, sql: String) {
-        self.to_do_statements.lock().unwrap().add(sql).unwrap();
+    pub fn add_event(&mut self, event: LogbookEvent) {
+        self.to_do_events.lock().unwrap().add(event).unwrap();
+    }
+
+    /// Writes out all queued events; the events stay queued until the db is reachable.
+    /// An event which fails is retried once on a new connection (it might have been dropped) and skipped if it fails again.
+    /// @return false if the db is not reachable
+    fn store_queued(q: &Mutex<Queue<LogbookEvent>>, repository: &mut Option<Box<dyn LogbookRepository>>) -> bool {
+        let mut new_connection = false;
+        if repository.is_none() {
+            match logbook_repository::get_logbook_repository() {
+                Ok(r) => *repository = Some(r),
+                Err(e) => {
+                    error!("{e}");
+                    return false;
+                }
+            }
+            new_connection = true;
+        }
+        let repo = repository.as_mut().unwrap();
+
+        loop {
+            let event = match q.lock().unwrap().peek() {
+                Ok(event) => event,
+                Err(_) => break,
+            };
+
+            match entry_builder::store_event(repo, &event) {
+                Ok(_) => new_connection = false,
+                Err(e) if new_connection => error!("Skipping {} of {} at {}: {e}", event.event, event.address, event.ts),
+                Err(e) => {
+                    warn!("{e}; reconnecting");
+                    *repository = None;
+                    return false;
+                }
+            }
+
+            q.lock().unwrap().remove().unwrap();
+        }
+
+        true
     }
 
     pub fn start(&mut self) {
@@ -46,32 +87,32 @@ impl DbThread {
         }
 
         // vars used by the thread internally:
-        let q = Arc::clone(&self.to_do_statements);
+        let q = Arc::clone(&self.to_do_events);
         let do_run = Arc::clone(&self.do_run);
-        let pool = Arc::clone(&self.pool);
 
         let thread = thread::spawn(
             move || {
+                let mut repository: Option<Box<dyn LogbookRepository>> = None;
+
                 while do_run.load(Ordering::Relaxed) {
                     let num_queued = q.lock().unwrap().size();
                     if num_queued == 0 {
                         thread::sleep(Duration::from_millis(500));    
                         continue;
                     }
-                    
-                    let mut conn = pool.get_conn().expect("Could not get connection from pool!");
-
-                    while q.lock().unwrap().size() > 0 {
-                        let sql = q.lock().unwrap().remove().unwrap();
-                        match conn.query_drop(&sql) {
-                            Ok(_) => (),
-                            Err(e) => error!("Error when executing query '{sql}': {e}")
-                        };
+
+                    if !DbThread::store_queued(&q, &mut repository) {
+                        thread::sleep(Duration::from_secs(5));
                     }
                 }
+
+                // write out what's left (a dropped connection gets one reconnect):
+                if !DbThread::store_queued(&q, &mut repository) && !DbThread::store_queued(&q, &mut repository) {
+                    error!("Dropped {} logbook events, the db is not reachable", q.lock().unwrap().size());
+                }
         });
 
         self.thread = Some(thread);
     }
 
-}
\ No newline at end of file
+}
@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::fmt::Debug;
+use std::sync::Arc;
 
-use chrono::Utc;
+use crate::clock::Clock;
 
 struct EDValue<T> {
     val: T,
@@ -22,19 +23,22 @@ pub struct ExpiringDict <T:Eq+Hash+Clone, U> {
     dict: HashMap<T, EDValue<U>>,
     ttl: i64,
     last_tick_ts: i64,
+    clock: Arc<dyn Clock>,
 }
 
 impl <T:Eq+Hash+Clone+Debug, U> ExpiringDict<T, U> {
-    pub fn new(ttl: i64) -> ExpiringDict<T, U> {
+    /// @param ttl: [ms]
+    pub fn new(ttl: i64, clock: Arc<dyn Clock>) -> ExpiringDict<T, U> {
         ExpiringDict {
             dict: HashMap::new(),
             ttl,
             last_tick_ts: 0,
+            clock,
         }
     }
 
     pub fn insert(&mut self, key: T, val: U) {
-        let ts = Utc::now().timestamp_millis();
+        let ts = self.clock.now_millis();
         let value = EDValue::new(val, ts);
         self.dict.insert(key, value);
     }
@@ -54,7 +58,7 @@ impl <T:Eq+Hash+Clone+Debug, U> ExpiringDict<T, U> {
 
     // This needs to be called periodically from <somewhere> to drop expired records.
     pub fn tick(&mut self) {
-        let now = Utc::now().timestamp_millis();
+        let now = self.clock.now_millis();
         if now - self.last_tick_ts < self.ttl { return; }
         self.last_tick_ts = now;
 
@@ -66,4 +70,33 @@ impl <T:Eq+Hash+Clone+Debug, U> ExpiringDict<T, U> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::clock::SimulatedClock;
+
+    use super::ExpiringDict;
+
+    #[test]
+    fn records_expire_after_ttl() {
+        let clock = Arc::new(SimulatedClock::new(1_700_000_000));
+        let mut dict: ExpiringDict<String, bool> = ExpiringDict::new(1000, clock.clone());
+
+        dict.insert("a".into(), true);
+        dict.tick();
+        assert!(dict.contains_key(&"a".into()));
+
+        clock.advance_to(1_700_000_001);
+        dict.insert("b".into(), true);
+        dict.tick();
+        assert!(dict.contains_key(&"a".into()));    // exactly at the ttl
+
+        clock.advance_to(1_700_000_002);
+        dict.tick();
+        assert!(!dict.contains_key(&"a".into()));
+        assert!(dict.contains_key(&"b".into()));
+    }
+}
@@ -0,0 +1,452 @@
+use std::sync::Arc;
+
+use ogn_client::data_structures::{AircraftBeacon, AircraftType};
+
+use crate::db::data_structures::{EVENT_GO_AROUND, EVENT_LOW_PASS, EVENT_TOUCH_AND_GO};
+use crate::worker::data_structures::{AircraftStatus, AircraftStatusWithTs, ApproachInfo, PhaseCandidate};
+use crate::detection_profiles::{DetectionMode, DetectionProfile, DetectionProfiles};
+
+const MAX_FLIGHT_TIME: i64 = 12 * 3600; // [s] anything longer is a relic from the previous day
+const TOUCH_AND_GO_WINDOW: i64 = 30;    // [s] max time on the ground for a touch-and-go
+const LOW_PASS_MAX_AGL: i32 = 30;       // [m]
+const LOW_PASS_MIN_GS: f64 = 140.0;     // [km/h]
+
+/// The part of an aircraft beacon the flight phase detection is based on.
+#[derive(Debug, Clone)]
+pub struct BeaconSample {
+    pub ts: i64,            // UTC [s]
+    pub lat: f64,
+    pub lon: f64,
+    pub gs: f64,            // [km/h]
+    pub climb_rate: f64,    // [m/s]
+    pub agl: Option<i32>,   // [m]
+    pub aircraft_type: AircraftType,
+}
+
+impl BeaconSample {
+    pub fn new(beacon: &AircraftBeacon, agl: Option<i32>) -> BeaconSample {
+        Self {
+            ts: beacon.ts as i64,
+            lat: beacon.lat,
+            lon: beacon.lon,
+            gs: beacon.speed as f64,
+            climb_rate: beacon.climb_rate,
+            agl,
+            aircraft_type: beacon.aircraft_type.clone(),
+        }
+    }
+}
+
+/// Everything we need to remember about an aircraft between two beacons.
+#[derive(Debug, Clone)]
+pub struct FlightState {
+    pub status: AircraftStatusWithTs,
+    pub gs: f64,    // [km/h] filtered ground speed; 0 = unknown
+    pub approach: Option<ApproachInfo>,
+    pub candidate: Option<PhaseCandidate>,
+}
+
+impl FlightState {
+    pub fn new(status: AircraftStatusWithTs, gs: f64, approach: Option<ApproachInfo>) -> FlightState {
+        Self {
+            status,
+            gs,
+            approach,
+            candidate: None,
+        }
+    }
+
+    /// State of an aircraft we have no prior information about.
+    pub fn unknown(ts: i64) -> FlightState {
+        FlightState::new(AircraftStatusWithTs::new(AircraftStatus::Unknown, ts), 0_f64, None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightPhaseEvent {
+    pub event: char,    // 'T', 'L' or one of the EVENT_* types
+    pub ts: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub icao_location: Option<String>,
+    pub flight_time: i64,   // [s] landings only
+}
+
+impl FlightPhaseEvent {
+    pub fn new(event: char, ts: i64, lat: f64, lon: f64, icao_location: Option<String>, flight_time: i64) -> FlightPhaseEvent {
+        Self {
+            event,
+            ts,
+            lat,
+            lon,
+            icao_location,
+            flight_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub state: Option<FlightState>,    // None = forget everything about the aircraft
+    pub events: Vec<FlightPhaseEvent>,
+}
+
+/// Take-off / landing state machine. Holds no I/O - prior state comes in, new state and events go out.
+pub struct FlightPhaseDetector {
+    profiles: Arc<DetectionProfiles>,
+}
+
+impl FlightPhaseDetector {
+
+    pub fn new(profiles: Arc<DetectionProfiles>) -> FlightPhaseDetector {
+        Self {
+            profiles,
+        }
+    }
+
+    /// Switches to reloaded profiles (see detection_profiles::profiles()).
+    pub fn set_profiles(&mut self, profiles: Arc<DetectionProfiles>) {
+        self.profiles = profiles;
+    }
+
+    /// Go-around = descent towards the airfield followed by a climb without ground contact;
+    /// low pass = the same at very low height and high speed.
+    fn classify_approach(approach: &ApproachInfo, profile: &DetectionProfile) -> Option<char> {
+        if approach.min_vs > profile.go_around_min_sink {   // no descent, just flying low around
+            return None;
+        }
+
+        if approach.min_agl <= LOW_PASS_MAX_AGL && approach.low_gs >= LOW_PASS_MIN_GS {
+            Some(EVENT_LOW_PASS)
+        } else {
+            Some(EVENT_GO_AROUND)
+        }
+    }
+
+    /// A landing near an airfield is deferred until the next beacon rules out a touch-and-go. Trackers often
+    /// get switched off right after the landing though - then the landing at the first ground contact is
+    /// confirmed here once the touch-and-go window has passed (see RedisReaper).
+    /// @param prior: state after the last beacon
+    /// @param now: UTC [s]
+    pub fn confirm_deferred_landing(&self, prior: &FlightState, aircraft_type: &AircraftType, now: i64) -> Detection {
+        let mut state = prior.clone();
+        let mut events: Vec<FlightPhaseEvent> = Vec::new();
+
+        let approach = match &prior.approach {
+            Some(approach) if approach.touched && prior.status.is(AircraftStatus::Airborne) && now - approach.low_ts >= TOUCH_AND_GO_WINDOW => approach,
+            _ => return Detection { state: Some(state), events },
+        };
+
+        let flight_time = approach.low_ts - prior.status.ts;  // [s]
+        if flight_time > MAX_FLIGHT_TIME {    // some relic from the previous day
+            return Detection { state: None, events };
+        }
+
+        state.status = AircraftStatusWithTs::new(AircraftStatus::OnGround, approach.low_ts);
+        state.approach = None;
+        if flight_time >= self.profiles.get(aircraft_type).min_flight_time {
+            events.push(FlightPhaseEvent::new('L', approach.low_ts, approach.low_lat, approach.low_lon, Some(approach.icao.clone()), flight_time));
+        }
+
+        Detection { state: Some(state), events }
+    }
+
+    pub fn profile(&self, aircraft_type: &AircraftType) -> &DetectionProfile {
+        self.profiles.get(aircraft_type)
+    }
+
+    /// @param sample: current beacon
+    /// @param prior: state after the previous beacon (FlightState::unknown() if there is none)
+    /// @param nearest_airfield: (lat, lon) -> code of the airfield (or launch site) at given position
+    pub fn detect(&self, sample: &BeaconSample, prior: &FlightState, nearest_airfield: &dyn Fn(f64, f64) -> Option<String>) -> Detection {
+        let profile = self.profiles.get(&sample.aircraft_type);
+
+        match (profile.mode, sample.agl) {
+            (DetectionMode::FootLaunch | DetectionMode::Hover, Some(agl)) => self.detect_by_dwell(sample, agl, prior, profile, nearest_airfield),
+            _ => self.detect_by_speed(sample, prior, profile, nearest_airfield),   // also when we don't know the terrain
+        }
+    }
+
+    /// Take-off = sustained height above the launch site, landing = sustained low height at low speed
+    /// (and for hovering aircraft also no vertical movement).
+    fn detect_by_dwell(&self, sample: &BeaconSample, agl: i32, prior: &FlightState, profile: &DetectionProfile, nearest_airfield: &dyn Fn(f64, f64) -> Option<String>) -> Detection {
+        let ts = sample.ts;
+        let mut state = prior.clone();
+        let mut events: Vec<FlightPhaseEvent> = Vec::new();
+
+        if state.status.is(AircraftStatus::Unknown) {   // we have no prior information
+            state.status = AircraftStatusWithTs::new(AircraftStatus::OnGround, ts);
+            state.candidate = None;
+        }
+        state.gs = sample.gs.round();
+
+        let status_changing = if state.status.is(AircraftStatus::OnGround) {
+            agl > profile.takeoff_min_agl
+        } else {
+            let stationary = profile.mode != DetectionMode::Hover || sample.climb_rate.abs() <= profile.max_vs;
+            agl <= profile.landing_max_agl && sample.gs <= profile.landing_gs && stationary
+        };
+
+        if !status_changing {
+            state.candidate = None;
+            return Detection { state: Some(state), events };
+        }
+
+        let candidate = *state.candidate.get_or_insert(PhaseCandidate::new(ts, sample.lat, sample.lon));
+        if ts - candidate.ts < profile.dwell_time {
+            return Detection { state: Some(state), events };
+        }
+        state.candidate = None;
+
+        if state.status.is(AircraftStatus::OnGround) {
+            state.status = AircraftStatusWithTs::new(AircraftStatus::Airborne, candidate.ts);
+            events.push(FlightPhaseEvent::new('T', candidate.ts, candidate.lat, candidate.lon, nearest_airfield(candidate.lat, candidate.lon), 0));
+
+        } else {
+            let flight_time = candidate.ts - state.status.ts;  // [s]
+            if flight_time > MAX_FLIGHT_TIME {    // some relic from the previous day
+                return Detection { state: None, events };
+            }
+
+            state.status = AircraftStatusWithTs::new(AircraftStatus::OnGround, candidate.ts);
+            if flight_time >= profile.min_flight_time {
+                events.push(FlightPhaseEvent::new('L', candidate.ts, candidate.lat, candidate.lon, nearest_airfield(candidate.lat, candidate.lon), flight_time));
+            }
+        }
+
+        Detection { state: Some(state), events }
+    }
+
+    fn detect_by_speed(&self, sample: &BeaconSample, prior: &FlightState, profile: &DetectionProfile, nearest_airfield: &dyn Fn(f64, f64) -> Option<String>) -> Detection {
+        let ts = sample.ts;
+        let prev_status = prior.status;
+        let mut state = prior.clone();
+        let mut events: Vec<FlightPhaseEvent> = Vec::new();
+
+        if prev_status.is(AircraftStatus::Unknown) { // we have no prior information
+            state.status = AircraftStatusWithTs::new(AircraftStatus::OnGround, ts);
+            state.gs = 0_f64;
+        }
+
+        let mut gs = sample.gs;  // [km/h]
+        if state.gs > 0_f64 { // filter speed change a bit (sometimes there are glitches in speed with badly placed gps antenna):
+            gs = gs * 0.7 + state.gs * 0.3;
+        }
+        if gs > 0_f64 {
+            state.gs = gs.round();
+        }
+
+        let takeoff_gs = profile.takeoff_gs;
+        let landing_gs = profile.landing_gs;
+
+        let mut current_status = AircraftStatusWithTs::new(AircraftStatus::Unknown, ts);
+        if prev_status.is(AircraftStatus::OnGround) {
+            current_status.status = if gs > takeoff_gs { AircraftStatus::Airborne } else { AircraftStatus::OnGround };
+        } else {    // when airborne
+            current_status.status = if gs <= landing_gs { AircraftStatus::OnGround } else { AircraftStatus::Airborne };
+        }
+
+        // low flight around airfields - touch-and-goes, go-arounds and low passes:
+        let mut event_lat = sample.lat;
+        let mut event_lon = sample.lon;
+        if prev_status.is(AircraftStatus::Airborne) {
+            match state.approach.take() {
+                Some(approach) if approach.touched => {
+                    if ts - approach.low_ts < TOUCH_AND_GO_WINDOW {
+                        if gs > takeoff_gs {  // airborne again shortly after the ground contact
+                            events.push(FlightPhaseEvent::new(EVENT_TOUCH_AND_GO, approach.low_ts, approach.low_lat, approach.low_lon, Some(approach.icao), 0));
+                        } else {
+                            state.approach = Some(approach);    // still waiting for the landing confirmation
+                        }
+                        return Detection { state: Some(state), events };
+                    }
+
+                    // on the ground for long enough -> the landing at the first ground contact is confirmed:
+                    current_status = AircraftStatusWithTs::new(AircraftStatus::OnGround, approach.low_ts);
+                    event_lat = approach.low_lat;
+                    event_lon = approach.low_lon;
+                },
+                approach => {
+                    let low_flight = match sample.agl {
+                        Some(agl) => agl <= profile.landing_max_agl && ts - prev_status.ts >= profile.min_flight_time,
+                        None => false,
+                    };
+                    let icao_location = if low_flight { nearest_airfield(sample.lat, sample.lon) } else { None };
+
+                    if let Some(icao_location) = icao_location {
+                        let agl = sample.agl.unwrap();
+                        let mut approach = approach.unwrap_or(ApproachInfo::new(icao_location, ts, sample.lat, sample.lon, agl, gs, sample.climb_rate));
+                        approach.update(ts, sample.lat, sample.lon, agl, gs, sample.climb_rate);
+
+                        if current_status.is(AircraftStatus::OnGround) {    // defer the landing - it might be a touch-and-go
+                            approach.touch(ts, sample.lat, sample.lon, gs);
+                            state.approach = Some(approach);
+                            return Detection { state: Some(state), events };
+                        }
+                        state.approach = Some(approach);
+
+                    } else if let Some(approach) = approach {  // left the airfield vicinity or climbed away
+                        if let Some(event) = FlightPhaseDetector::classify_approach(&approach, profile) {
+                            events.push(FlightPhaseEvent::new(event, approach.low_ts, approach.low_lat, approach.low_lon, Some(approach.icao), 0));
+                        }
+                    }
+                },
+            }
+        }
+
+        if current_status.status != prev_status.status {
+            let event = if current_status.is(AircraftStatus::OnGround) {'L'} else {'T'}; // L = landing, T = take-off
+            let mut flight_time: i64 = 0;
+
+            if event == 'L' {
+                flight_time = current_status.ts - prev_status.ts;   // [s]
+                if flight_time < profile.min_flight_time {
+                    return Detection { state: Some(state), events };
+                }
+
+                if flight_time > MAX_FLIGHT_TIME {    // some relic from the previous day
+                    return Detection { state: None, events };
+                }
+
+                // check altitude above ground level:
+                if sample.agl.is_some() && sample.agl.unwrap() > profile.landing_max_agl {    // most likely a false detection
+                    return Detection { state: Some(state), events };
+                }
+
+            } else if event == 'T' {
+                // check altitude above ground level:
+                if sample.agl.is_some() && sample.agl.unwrap() < profile.takeoff_min_agl {  // most likely a false detection
+                    return Detection { state: Some(state), events };
+                }
+            }
+
+            state.status = current_status;
+            let icao_location = nearest_airfield(event_lat, event_lon);
+            events.push(FlightPhaseEvent::new(event, current_status.ts, event_lat, event_lon, icao_location, flight_time));
+        }
+
+        Detection { state: Some(state), events }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ogn_client::data_structures::AircraftType;
+
+    use super::{BeaconSample, FlightPhaseDetector, FlightState};
+    use crate::detection_profiles::{DetectionMode, DetectionProfile, DetectionProfiles};
+    use crate::worker::data_structures::{AircraftStatus, AircraftStatusWithTs};
+
+    const AF_LAT: f64 = 49.0;
+    const AF_LON: f64 = 16.0;
+
+    fn nearest_airfield(lat: f64, lon: f64) -> Option<String> {
+        if (lat - AF_LAT).abs() < 0.05 && (lon - AF_LON).abs() < 0.05 { Some("LKXX".into()) } else { None }
+    }
+
+    /// (ts, gs, agl, climb rate) -> events produced along the way and by the reaper a while after the last beacon
+    fn run(aircraft_type: AircraftType, samples: &[(i64, f64, i32, f64)]) -> Vec<(char, i64)> {
+        let detector = FlightPhaseDetector::new(Arc::new(DetectionProfiles::default()));
+        let mut state = FlightState::unknown(samples[0].0);
+        let mut events = Vec::new();
+
+        for (ts, gs, agl, vs) in samples.iter() {
+            let sample = BeaconSample { ts: *ts, lat: AF_LAT, lon: AF_LON, gs: *gs, climb_rate: *vs, agl: Some(*agl), aircraft_type: aircraft_type.clone() };
+            let detection = detector.detect(&sample, &state, &nearest_airfield);
+            for e in detection.events {
+                events.push((e.event, e.ts));
+            }
+            state = detection.state.unwrap_or(FlightState::unknown(*ts));
+        }
+
+        let now = samples.last().unwrap().0 + 300;
+        let detection = detector.confirm_deferred_landing(&state, &aircraft_type, now);
+        events.extend(detection.events.iter().map(|e| (e.event, e.ts)));
+
+        events
+    }
+
+    #[test]
+    fn recorded_sequences() {
+        let cases: Vec<(&str, AircraftType, Vec<(i64, f64, i32, f64)>, Vec<(char, i64)>)> = vec![
+            ("glider flight", AircraftType::Glider, vec![
+                (0, 0.0, 0, 0.0), (10, 60.0, 0, 0.0), (20, 95.0, 10, 1.0), (30, 100.0, 60, 3.0),
+                (600, 100.0, 500, 0.0), (1200, 100.0, 400, -1.0),
+                (1300, 90.0, 80, -2.0), (1310, 70.0, 20, -2.0), (1320, 15.0, 0, -1.0), (1330, 0.0, 0, 0.0),
+                (1370, 0.0, 0, 0.0),
+            ], vec![('T', 30), ('L', 1330)]),
+
+            ("landing then silence", AircraftType::Glider, vec![
+                (0, 0.0, 0, 0.0), (10, 60.0, 0, 0.0), (20, 95.0, 10, 1.0), (30, 100.0, 60, 3.0),
+                (600, 100.0, 500, 0.0), (1200, 100.0, 400, -1.0),
+                (1300, 90.0, 80, -2.0), (1310, 70.0, 20, -2.0), (1320, 15.0, 0, -1.0), (1330, 0.0, 0, 0.0),
+            ], vec![('T', 30), ('L', 1330)]),
+
+            ("tow plane touch-and-go", AircraftType::TowPlane, vec![
+                (0, 0.0, 0, 0.0), (10, 120.0, 60, 3.0), (20, 130.0, 100, 3.0),
+                (600, 150.0, 400, -2.0),
+                (700, 120.0, 90, -3.0), (710, 100.0, 5, -2.0), (715, 40.0, 0, 0.0), (720, 30.0, 0, 0.0),
+                (730, 130.0, 10, 2.0), (740, 140.0, 60, 4.0), (750, 150.0, 150, 4.0),
+                (1200, 120.0, 80, -3.0), (1210, 40.0, 0, 0.0), (1220, 10.0, 0, 0.0), (1260, 0.0, 0, 0.0),
+            ], vec![('T', 10), ('N', 720), ('L', 1220)]),
+
+            ("powered go-around", AircraftType::PoweredAircraft, vec![
+                (0, 0.0, 0, 0.0), (10, 120.0, 60, 3.0),
+                (600, 150.0, 400, -2.0),
+                (700, 120.0, 90, -3.0), (710, 110.0, 40, -3.0), (720, 120.0, 60, 3.0), (730, 130.0, 150, 5.0),
+            ], vec![('T', 10), ('G', 710)]),
+
+            ("glider low pass", AircraftType::Glider, vec![
+                (0, 0.0, 0, 0.0), (10, 60.0, 0, 0.0), (20, 100.0, 60, 3.0), (30, 100.0, 100, 3.0),
+                (600, 180.0, 300, -5.0),
+                (700, 180.0, 90, -4.0), (710, 180.0, 15, -2.0), (720, 170.0, 40, 4.0), (730, 150.0, 120, 5.0),
+            ], vec![('T', 20), ('P', 710)]),
+
+            ("glider low save", AircraftType::Glider, vec![
+                (0, 0.0, 0, 0.0), (10, 60.0, 0, 0.0), (20, 95.0, 10, 1.0), (30, 100.0, 60, 3.0),
+                (600, 100.0, 300, -1.0),
+                (1200, 90.0, 90, -1.5), (1260, 85.0, 70, -2.0), (1320, 85.0, 65, -0.5), (1380, 80.0, 80, 1.5), (1440, 80.0, 150, 2.0),
+            ], vec![('T', 30)]),
+
+            ("hop shorter than the min flight time", AircraftType::PoweredAircraft, vec![
+                (0, 0.0, 0, 0.0), (10, 120.0, 60, 3.0), (60, 30.0, 0, -1.0), (70, 0.0, 0, 0.0),
+            ], vec![('T', 10)]),
+
+            ("paraglider from a hill", AircraftType::Paraglider, vec![
+                (0, 0.0, 0, 0.0), (30, 0.0, 0, 0.0), (60, 25.0, 10, 1.0), (70, 30.0, 40, 1.0), (80, 30.0, 60, 1.0), (100, 35.0, 80, 0.0),
+                (1000, 30.0, 200, -1.0),
+                (1800, 25.0, 20, -1.0), (1810, 5.0, 5, -1.0), (1820, 0.0, 0, 0.0), (1850, 0.0, 0, 0.0),
+            ], vec![('T', 70), ('L', 1810)]),
+
+            ("helicopter hovering before landing", AircraftType::Helicopter, vec![
+                (0, 0.0, 0, 0.0), (10, 0.0, 5, 2.0), (20, 0.0, 25, 2.0), (30, 20.0, 40, 1.0), (50, 60.0, 80, 1.0), (80, 80.0, 120, 1.0),
+                (600, 0.0, 8, 0.0), (620, 0.0, 8, 0.0), (640, 0.0, 8, 0.0), (650, 50.0, 40, 2.0),
+                (1200, 10.0, 30, -2.0), (1210, 0.0, 5, -1.0), (1220, 0.0, 0, 0.0), (1250, 0.0, 0, 0.0), (1290, 0.0, 0, 0.0),
+            ], vec![('T', 20), ('L', 1220)]),
+
+            ("first beacon already airborne", AircraftType::Glider, vec![
+                (0, 100.0, 300, 0.0), (10, 100.0, 300, 0.0),
+            ], vec![('T', 0)]),
+        ];
+
+        for (name, aircraft_type, samples, expected) in cases.iter() {
+            let events = run(aircraft_type.clone(), samples);
+            assert_eq!(&events, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn relic_from_previous_day_is_forgotten() {
+        let detector = FlightPhaseDetector::new(Arc::new(DetectionProfiles::default()));
+        let prior = FlightState::new(AircraftStatusWithTs::new(AircraftStatus::Airborne, 0), 10_f64, None);
+        let sample = BeaconSample { ts: 13 * 3600, lat: 0.0, lon: 0.0, gs: 0.0, climb_rate: 0.0, agl: Some(0), aircraft_type: AircraftType::Glider };
+
+        let detection = detector.detect(&sample, &prior, &nearest_airfield);
+
+        assert!(detection.state.is_none());
+        assert!(detection.events.is_empty());
+    }
+}
@@ -1,91 +1,255 @@
-use log::{info, error};
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, error};
 
 use gdal::Dataset;
+use gdal::errors::GdalError;
 use gdal::spatial_ref::{SpatialRef, CoordTransform};
-// use gdal::raster::RasterBand;
 
-pub struct GeoFile { 
-    xsize: i64,
-    ysize: i64,
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(60);   // how often the file is checked for modification
+const MIN_CACHED_BLOCKS: usize = 4;                 // the 2x2 cells of the interpolation may span four blocks
+
+const WGS84_LAT_LON_WKT: &str = "GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",7030]],TOWGS84[0,0,0,0,0,0,0],AUTHORITY[\"EPSG\",6326]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",8901]],UNIT[\"DMSH\",0.0174532925199433,AUTHORITY[\"EPSG\",9108]],AXIS[\"Lat\",NORTH],AXIS[\"Long\",EAST],AUTHORITY[\"EPSG\",4326]]";
+
+/// A decoded raster block.
+struct Block {
+    data: Vec<f32>,
+    width: usize,
+    last_used: u64,
+}
+
+/// Terrain elevation from a single-band raster (GeoTIFF). Raster blocks are read once and kept
+/// in a LRU cache; the elevation is interpolated between the four nearest cells.
+pub struct GeoFile {
+    filepath: String,
+    mtime: Option<SystemTime>,
+    last_check: Instant,
+    xsize: usize,
+    ysize: usize,
     dataset: Dataset,
-    // band: RasterBand<'a>,
     geotransform: [f64; 6],
-    ct: CoordTransform,
+    ct: Option<CoordTransform>,     // None = the raster is in WGS84 already
+    bounds: (f64, f64, f64, f64),   // [deg] min lat, max lat, min lon, max lon
+    block_size: (usize, usize),
+    no_data: Option<f64>,
+    cache_size: usize,  // [B]
+    blocks: HashMap<(usize, usize), Block>,
+    max_blocks: usize,
+    tick: u64,
 }
 
 impl GeoFile {
 
-    pub fn new(geotiff_filepath: &str) -> GeoFile {
-        info!("Reading geotiff from '{geotiff_filepath}'");
+    /// @param cache_size: [B] memory for the decoded raster blocks
+    pub fn open(geotiff_filepath: &str, cache_size: usize) -> Result<GeoFile, GdalError> {
+        debug!("Reading geotiff from '{geotiff_filepath}'");
 
-        let dataset = Dataset::open(geotiff_filepath).unwrap();
-        // println!("This {} is in '{}' and has {} bands.", dataset.driver().long_name(), dataset.spatial_ref().unwrap().name().unwrap(), dataset.raster_count());
-        
-        // let band = dataset.rasterband(1).unwrap();
+        let mtime = fs::metadata(geotiff_filepath).and_then(|m| m.modified()).ok();
+        let dataset = Dataset::open(geotiff_filepath)?;
 
         let (xsize, ysize) = dataset.raster_size();
-        // println!("RASTER SIZE {xsize} X {ysize}");
-    
-        let geotransform = dataset.geo_transform().unwrap();
-        
-        let src_ref = SpatialRef::from_wkt("GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",7030]],TOWGS84[0,0,0,0,0,0,0],AUTHORITY[\"EPSG\",6326]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",8901]],UNIT[\"DMSH\",0.0174532925199433,AUTHORITY[\"EPSG\",9108]],AXIS[\"Lat\",NORTH],AXIS[\"Long\",EAST],AUTHORITY[\"EPSG\",4326]]").unwrap();
-        let dst_ref = dataset.spatial_ref().unwrap();
-    
-        // let sn = src_ref.name().unwrap();
-        // let dn = dst_ref.name().unwrap();
-        // println!("CT from {sn} to {dn}");
-
-        let ct = CoordTransform::new(&src_ref, &dst_ref).unwrap();
-
-        Self {
-            xsize: xsize as i64,
-            ysize: ysize as i64,
-            dataset: dataset,
-            // band: band,
-            geotransform: geotransform,
-            ct: ct,
+        let geotransform = dataset.geo_transform()?;
+
+        let dst_ref = dataset.spatial_ref()?;
+        let wgs84 = dst_ref.auth_code().ok() == Some(4326);
+        let ct = if wgs84 {
+            None    // x = lon, y = lat; no transformation needed
+        } else {
+            let src_ref = SpatialRef::from_wkt(WGS84_LAT_LON_WKT).unwrap();
+            Some(CoordTransform::new(&src_ref, &dst_ref)?)
+        };
+
+        // the raster corners in WGS84:
+        let gt = &geotransform;
+        let mut xs = [gt[0], gt[0] + xsize as f64 * gt[1], gt[0], gt[0] + xsize as f64 * gt[1]];
+        let mut ys = [gt[3], gt[3], gt[3] + ysize as f64 * gt[5], gt[3] + ysize as f64 * gt[5]];
+        let (lats, lons) = if wgs84 {
+            (ys, xs)
+        } else {
+            let inverse_ct = CoordTransform::new(&dst_ref, &SpatialRef::from_wkt(WGS84_LAT_LON_WKT).unwrap())?;
+            inverse_ct.transform_coords(&mut xs, &mut ys, &mut [])?;
+            (xs, ys)
+        };
+        let bounds = (
+            lats.iter().cloned().fold(f64::MAX, f64::min), lats.iter().cloned().fold(f64::MIN, f64::max),
+            lons.iter().cloned().fold(f64::MAX, f64::min), lons.iter().cloned().fold(f64::MIN, f64::max),
+        );
+
+        let band = dataset.rasterband(1)?;
+        let block_size = band.block_size();
+        let no_data = band.no_data_value();
+        let max_blocks = (cache_size / (block_size.0 * block_size.1 * 4).max(1)).max(MIN_CACHED_BLOCKS);
+
+        Ok(Self {
+            filepath: geotiff_filepath.into(),
+            mtime,
+            last_check: Instant::now(),
+            xsize,
+            ysize,
+            dataset,
+            geotransform,
+            ct,
+            bounds,
+            block_size,
+            no_data,
+            cache_size,
+            blocks: HashMap::new(),
+            max_blocks,
+            tick: 0,
+        })
+    }
+
+    /// Reopens the file when it has been replaced; a broken file keeps the current one in use.
+    fn reload_if_modified(&mut self) {
+        if self.last_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        let mtime = fs::metadata(&self.filepath).and_then(|m| m.modified()).ok();
+        if mtime.is_none() || mtime == self.mtime {
+            return;
+        }
+
+        match GeoFile::open(&self.filepath, self.cache_size) {
+            Ok(geo_file) => *self = geo_file,
+            Err(e) => {
+                error!("Keeping the current terrain data, could not reload '{}': {e}", self.filepath);
+                self.mtime = mtime;
+            }
         }
     }
 
-    pub fn get_value(&mut self, lat:f64, lon: f64) -> Option<i64> {
-        // transform coordinates between spatial refs (from [lat,lon] to [x,y]):
-        let mut xs = [lat];
-        let mut ys = [lon];
-        let mut zs = [];
-        match self.ct.transform_coords(&mut xs, &mut ys, &mut zs) {
-            Ok(_) => (),
-            Err(_) => {
-                error!("Wrong argumens into transform_coords(): lat:'{:.4}'; lon:'{:.4}'", lat, lon);
-                return None // wrongly parsed coords were passed?
-            },
+    /// @return [deg] min lat, max lat, min lon, max lon
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        self.bounds
+    }
+
+    /// [deg] approximate cell size
+    pub fn resolution(&self) -> f64 {
+        (self.bounds.1 - self.bounds.0) / self.ysize as f64
+    }
+
+    /// [lat, lon] -> raster coordinates (in pixels, 0.0 = the top/left edge of the first cell)
+    fn to_raster(&self, lat: f64, lon: f64) -> Option<(f64, f64)> {
+        let (x, y) = match &self.ct {
+            None => (lon, lat),
+            Some(ct) => {
+                // transform coordinates between spatial refs (from [lat,lon] to [x,y]):
+                let mut xs = [lat];
+                let mut ys = [lon];
+                let mut zs = [];
+                if ct.transform_coords(&mut xs, &mut ys, &mut zs).is_err() {
+                    error!("Wrong argumens into transform_coords(): lat:'{:.4}'; lon:'{:.4}'", lat, lon);
+                    return None // wrongly parsed coords were passed?
+                }
+                (xs[0], ys[0])
+            }
+        };
+
+        let px = (x - self.geotransform[0]) / self.geotransform[1];
+        let py = (y - self.geotransform[3]) / self.geotransform[5];
+
+        Some((px, py))
+    }
+
+    fn read_block(&self, bx: usize, by: usize) -> Option<Block> {
+        let (bw, bh) = self.block_size;
+        let (x0, y0) = (bx * bw, by * bh);
+        let width = bw.min(self.xsize - x0);    // the edge blocks may be cut
+        let height = bh.min(self.ysize - y0);
+
+        let band = self.dataset.rasterband(1).ok()?;
+        match band.read_as::<f32>((x0 as isize, y0 as isize), (width, height), (width, height), None) {
+            Ok(buf) => Some(Block { data: buf.data().to_vec(), width, last_used: 0 }),
+            Err(e) => {
+                error!("Could not read raster block {bx}x{by} of '{}': {e}", self.filepath);
+                None
+            }
+        }
+    }
+
+    /// Elevation of a cell; None for no-data and nonsense values.
+    fn cell(&mut self, x: usize, y: usize) -> Option<f64> {
+        let (bw, bh) = self.block_size;
+        let key = (x / bw, y / bh);
+
+        self.tick += 1;
+        if !self.blocks.contains_key(&key) {
+            let block = self.read_block(key.0, key.1)?;
+            if self.blocks.len() >= self.max_blocks {
+                let lru = *self.blocks.iter().min_by_key(|(_, b)| b.last_used).map(|(k, _)| k).unwrap();
+                self.blocks.remove(&lru);
+            }
+            self.blocks.insert(key, block);
+        }
+
+        let block = self.blocks.get_mut(&key).unwrap();
+        block.last_used = self.tick;
+        let value = block.data[(y % bh) * block.width + (x % bw)] as f64;
+
+        if Some(value) == self.no_data || value < -10_994.0 || value > 100_000.0 { // below depth of Mariana Trench (10994m) or above space edge (100km)
+            return None;
+        }
+
+        Some(value)
+    }
+
+    /// @return [m] terrain elevation interpolated between the four nearest cells
+    pub fn get_elevation(&mut self, lat: f64, lon: f64) -> Option<f64> {
+        self.reload_if_modified();
+
+        let (px, py) = self.to_raster(lat, lon)?;
+        if !(px >= 0.0 && px < self.xsize as f64 && py >= 0.0 && py < self.ysize as f64) {
+            return None;
         }
-        
-        // let x = xs[0];
-        // let y = ys[0];
-        // println!("XY1: {x}; {y}");
-        
-        let xy = [xs[0], ys[0]];
-    
-        // calc xy coords pointing to the geotiff:
-        let x = ((xy[0] - self.geotransform[0]) / self.geotransform[1]).round() as i64;
-        let y = ((xy[1] - self.geotransform[3]) / self.geotransform[5]).round() as i64;
-        // println!("XY2: {x}; {y}");
-
-        // TODO if 0 <= x < self.dataset.RasterXSize and 0 <= y < self.dataset.RasterYSize:
-        if x >= 0 && x < self.xsize && y >= 0 && y < self.ysize {
-            // read out the value at the geotiff XY position:
-            let buf = self.dataset.rasterband(1).unwrap().read_as::<f64>((x as isize,y as isize), (1,1), (1,1), Option::None).unwrap();
-            let mut elevation = buf.data()[0].round() as i64;  // [m]
-            
-            if elevation < -10_994 || elevation > 100*1000 {    // below depth of Mariana Trench (10994m) or above space edge (100km)
-                elevation = 0;
+
+        // cell values are valid at the cell centers:
+        let (cx, cy) = ((px - 0.5).max(0.0), (py - 0.5).max(0.0));
+        let x0 = (cx.floor() as usize).min(self.xsize - 1);
+        let y0 = (cy.floor() as usize).min(self.ysize - 1);
+        let x1 = (x0 + 1).min(self.xsize - 1);
+        let y1 = (y0 + 1).min(self.ysize - 1);
+        let (tx, ty) = (cx - x0 as f64, cy - y0 as f64);
+
+        match (self.cell(x0, y0), self.cell(x1, y0), self.cell(x0, y1), self.cell(x1, y1)) {
+            (Some(v00), Some(v10), Some(v01), Some(v11)) => Some(bilinear(v00, v10, v01, v11, tx, ty)),
+            _ => {
+                // close to a no-data area - the nearest cell only (none if that is a void too, to let a coarser source answer):
+                let x = (px.floor() as usize).min(self.xsize - 1);
+                let y = (py.floor() as usize).min(self.ysize - 1);
+                self.cell(x, y)
             }
-            
-            return Some(elevation);
         }
-    
-        return None;
-        
     }
 
+    /// @return [m] terrain elevation rounded to whole meters
+    pub fn get_value(&mut self, lat:f64, lon: f64) -> Option<i64> {
+        self.get_elevation(lat, lon).map(|e| e.round() as i64)
+    }
+
+}
+
+/// @param v00, v10, v01, v11: values at the corners ([x, y])
+/// @param tx, ty: 0..1 position between the corners
+fn bilinear(v00: f64, v10: f64, v01: f64, v11: f64, tx: f64, ty: f64) -> f64 {
+    let top = v00 + (v10 - v00) * tx;
+    let bottom = v01 + (v11 - v01) * tx;
+
+    top + (bottom - top) * ty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bilinear;
+
+    #[test]
+    fn bilinear_interpolation() {
+        assert_eq!(bilinear(100.0, 200.0, 300.0, 400.0, 0.0, 0.0), 100.0);
+        assert_eq!(bilinear(100.0, 200.0, 300.0, 400.0, 1.0, 1.0), 400.0);
+        assert_eq!(bilinear(100.0, 200.0, 300.0, 400.0, 0.5, 0.0), 150.0);
+        assert_eq!(bilinear(100.0, 200.0, 300.0, 400.0, 0.5, 0.5), 250.0);
+    }
 }
@@ -0,0 +1,85 @@
+use std::time::Duration;
+use std::sync::Arc;
+
+use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crossbeam::channel::{unbounded, Sender, Receiver};
+use log::error;
+
+use crate::mqtt::{Mqtt, MqttMessage};
+
+/// Publishes the MQTT messages in a separate thread - a slow or unreachable broker must not hold up the beacon processing.
+pub struct MqttWorker {
+    thread: Option<thread::JoinHandle<()>>,
+    do_run: Arc<AtomicBool>,
+    mqtt: Option<Mqtt>,
+    sender: Sender<MqttMessage>,
+    receiver: Receiver<MqttMessage>,
+}
+
+impl MqttWorker {
+    pub fn new(mqtt: Mqtt) -> MqttWorker {
+        let (sender, receiver) = unbounded::<MqttMessage>();
+        Self {
+            thread: None,
+            do_run: Arc::new(AtomicBool::new(true)),
+            mqtt: Some(mqtt),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Stops the thread once the queued messages have been sent.
+    pub fn stop(&mut self) {
+        self.do_run.swap(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("joining the thread");
+        }
+    }
+
+    pub fn start(&mut self) {
+        let mut mqtt = match self.mqtt.take() {
+            Some(mqtt) => mqtt,
+            None => {
+                println!("[WARN] Refused to start mqtt_worker thread. The thread is already running!");
+                return;
+            }
+        };
+
+        // vars used by the thread internally:
+        let do_run = Arc::clone(&self.do_run);
+        let incoming = self.receiver.clone();
+
+        let thread = thread::spawn(move || {
+            while do_run.load(Ordering::Relaxed) {
+                let msg = incoming.recv_timeout(Duration::from_secs(1));   // not to miss the stop
+
+                if msg.is_err() {
+                    continue;
+                }
+
+                // send whatever has piled up meanwhile over a single connection:
+                let mut messages = vec![msg.unwrap()];
+                messages.extend(incoming.try_iter());
+                mqtt.send_mqtt_messages(&messages);
+            }
+
+            // send what's left:
+            let messages: Vec<MqttMessage> = incoming.try_iter().collect();
+            if messages.len() > 0 {
+                mqtt.send_mqtt_messages(&messages);
+            }
+        });
+
+        self.thread = Some(thread);
+    }
+
+    /// Enqueues a message for publishing.
+    pub fn send(&mut self, message: MqttMessage) {
+        if let Err(e) = self.sender.send(message) {
+            error!("When sending an mqtt message: {:?}", e);
+        }
+    }
+
+}
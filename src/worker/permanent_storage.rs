@@ -1,13 +1,11 @@
 
 use std::collections::{HashSet, HashMap};
 use std::sync::{Arc, Once};
-use log::warn;
+use log::{warn, error};
 
-use mysql::Row;
-use mysql::prelude::Queryable;
 use ogn_client::data_structures::AddressType;
 
-use crate::db::mysql::MySQL;
+use crate::db::logbook_repository;
 
 pub struct PermanentStorage {
     address_type: String,
@@ -23,20 +21,19 @@ impl PermanentStorage {
     }
 
     pub fn reload(&mut self) {
-        match MySQL::new() {
+        match logbook_repository::get_logbook_repository() {
             Err(e) => {
-                warn!("Could not obtain MySQL connection, skipping reload().");
+                warn!("Could not obtain db connection, skipping reload(): {e}");
             },
-            Ok(mut mysql) => {
-                let mut conn = mysql.get_connection();
-
-                let q = format!("SELECT addr FROM permanent_storage WHERE addr_type='{}' AND active=true", self.address_type);
-                let new_entries: Vec<String> = conn.query_map(q, 
-                    |mut row: Row| {
-                        let addr = row.take("addr").unwrap();
-                        addr
+            Ok(mut repository) => {
+                let address_type = AddressType::from_short_str(self.address_type.clone());
+                let new_entries = match repository.list_permanent_storage(&address_type) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        error!("{e}");
+                        return;
                     }
-                ).unwrap();
+                };
 
                 self.entries.clear();
                 for e in new_entries {
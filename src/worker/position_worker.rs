@@ -0,0 +1,123 @@
+use std::time::Duration;
+use std::sync::Arc;
+
+use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crossbeam::channel::{unbounded, Sender, Receiver};
+use chrono::{DateTime, Utc, NaiveDateTime};
+use log::error;
+
+use ogn_client::data_structures::AircraftBeacon;
+
+use crate::db::track_store::{self, Position};
+
+/// Writes the positions into the track store (InfluxDB or SQLite) in a separate thread.
+pub struct PositionWorker {
+    thread: Option<thread::JoinHandle<()>>,
+    do_run: Arc<AtomicBool>,
+    sender: Sender<Position>,
+    receiver: Receiver<Position>,
+}
+
+impl PositionWorker {
+    pub fn new() -> PositionWorker {
+        let (sender, receiver) = unbounded::<Position>();
+        Self {
+            thread: None,
+            do_run: Arc::new(AtomicBool::new(true)),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Stops the thread once the queued positions have been written.
+    pub fn stop(&mut self) {
+        self.do_run.swap(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("joining the thread");
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.thread.is_some() {
+            println!("[WARN] Refused to start position_worker thread. The thread is already running!");
+            return;
+        }
+
+        // vars used by the thread internally:
+        let do_run = Arc::clone(&self.do_run);
+        let incoming = self.receiver.clone();
+
+        let thread = thread::spawn(move || {
+            // the positions stay queued until the store can be opened:
+            let mut track_store = track_store::get_track_store();
+            while track_store.is_none() && do_run.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_secs(5));
+                track_store = track_store::get_track_store();
+            }
+            let mut track_store = match track_store {
+                Some(track_store) => track_store,
+                None => {
+                    error!("Dropped {} positions, the track store could not be opened", incoming.len());
+                    return;
+                }
+            };
+
+            while do_run.load(Ordering::Relaxed) {
+                let pos = incoming.recv_timeout(Duration::from_secs(1));   // not to miss the stop
+
+                if pos.is_err() {
+                    continue;
+                }
+                let pos = pos.unwrap();
+
+                if let Err(e) = track_store.insert_position(&pos) {
+                    error!("{e}");
+                }
+            }
+
+            // write out what's left:
+            for pos in incoming.try_iter() {
+                if let Err(e) = track_store.insert_position(&pos) {
+                    error!("{e}");
+                }
+            }
+        });
+
+        self.thread = Some(thread);
+    }
+
+    /// Enqueues a beacon for the track store insertion.
+    /// @param agl: height above the terrain [m] if known
+    pub fn store(&mut self, beacon: &AircraftBeacon, agl: Option<i32>) {
+        match self.sender.send(beacon_into_position(beacon, agl)) {
+            Ok(_) => (),
+            Err(e) => error!("When storing a beacon: {:?}", e),
+        }
+    }
+
+}
+
+/// @param agl: height above the terrain [m]; stored as 0 if unknown
+pub(crate) fn beacon_into_position(beacon: &AircraftBeacon, agl: Option<i32>) -> Position{
+    // time                addr      agl alt gs lat       lon       tr vs ss
+    // 1655046041000000000 OGN414931 0   504 0  49.368367 16.114133 0  0  123
+
+    let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp_opt(beacon.ts as i64, 0).unwrap(), Utc);
+
+    let position = Position {
+        time: dt,
+        addr: format!("{}{}", beacon.addr_type.as_long_str(), beacon.addr),
+        agl: agl.unwrap_or(0),
+        alt: beacon.altitude,
+        gs: beacon.speed,
+        lat: beacon.lat,
+        lon: beacon.lon,
+        tr: beacon.turn_rate,
+        vs: beacon.climb_rate,
+        ss: beacon.signal_strength,
+    };
+
+    position
+}
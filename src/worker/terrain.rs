@@ -0,0 +1,190 @@
+//! Terrain elevation from several digital elevation models. A directory of tiles (SRTM / Copernicus
+//! .hgt or GeoTIFF) is searched first, the finest resolution first; the coarse global mosaic answers
+//! wherever no tile does.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use lazy_static::lazy_static;
+use log::{error, info};
+
+use crate::configuration::{get_terrain_cache_size, DEM_DIRPATH, GEOTIFF_FILEPATH};
+use crate::worker::geo_file::GeoFile;
+
+const TILE_EXTENSIONS: [&str; 3] = ["hgt", "tif", "tiff"];
+const MAX_OPEN_TILES: usize = 16;
+const TILE_CACHE_SIZE: usize = 8 * 1024 * 1024;    // [B] per open tile
+
+lazy_static! {
+    static ref SHARED_TERRAIN: Mutex<Terrain> = Mutex::new(Terrain::new(DEM_DIRPATH, GEOTIFF_FILEPATH));
+    static ref TILE_INDEXES: Mutex<HashMap<String, Arc<Vec<TileInfo>>>> = Mutex::new(HashMap::new());
+}
+
+/// A DEM tile as found by the directory scan; shared by all the Terrain instances.
+struct TileInfo {
+    filepath: String,
+    name: String,
+    bounds: (f64, f64, f64, f64),   // [deg] min lat, max lat, min lon, max lon
+    resolution: f64,                // [deg]
+}
+
+impl TileInfo {
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        lat >= self.bounds.0 && lat < self.bounds.1 && lon >= self.bounds.2 && lon < self.bounds.3
+    }
+}
+
+/// The GDAL handle of a tile, one per Terrain instance.
+#[derive(Default)]
+struct Tile {
+    geo_file: Option<GeoFile>,      // opened when needed
+    last_used: u64,
+    broken: bool,
+}
+
+pub struct Terrain {
+    index: Arc<Vec<TileInfo>>,  // ordered by resolution, the finest first
+    tiles: Vec<Tile>,           // in the order of the index
+    fallback: Option<GeoFile>,
+    fallback_name: String,
+    tick: u64,
+}
+
+// The GDAL handles are not bound to the thread which opened them; a Terrain is used by one thread
+// at a time only (owned by a worker or behind the mutex of the shared one).
+unsafe impl Send for Terrain {}
+
+impl Terrain {
+    /// @param tiles_dirpath: directory of DEM tiles (may not exist)
+    /// @param fallback_filepath: coarse raster covering everything else (may not exist)
+    pub fn new(tiles_dirpath: &str, fallback_filepath: &str) -> Terrain {
+        let index = Terrain::tile_index(tiles_dirpath);
+
+        let fallback = if Path::new(fallback_filepath).exists() {
+            match GeoFile::open(fallback_filepath, get_terrain_cache_size()) {
+                Ok(geo_file) => Some(geo_file),
+                Err(e) => {
+                    error!("Could not open terrain fallback '{fallback_filepath}': {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        info!("Terrain: {} DEM tiles in '{tiles_dirpath}', fallback '{fallback_filepath}'{}", index.len(), if fallback.is_none() { " not available" } else { "" });
+
+        Terrain {
+            tiles: index.iter().map(|_| Tile::default()).collect(),
+            index,
+            fallback,
+            fallback_name: file_name(fallback_filepath),
+            tick: 0,
+        }
+    }
+
+    /// The tiles directory is scanned by the first Terrain only.
+    fn tile_index(dirpath: &str) -> Arc<Vec<TileInfo>> {
+        let mut indexes = TILE_INDEXES.lock().unwrap();
+        let index = indexes.entry(dirpath.into()).or_insert_with(|| Arc::new(Terrain::scan_tiles(dirpath)));
+
+        Arc::clone(index)
+    }
+
+    fn scan_tiles(dirpath: &str) -> Vec<TileInfo> {
+        let entries = match fs::read_dir(dirpath) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),    // no tiles
+        };
+
+        let mut tiles = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+            if !TILE_EXTENSIONS.contains(&extension.as_str()) {
+                continue;
+            }
+
+            let filepath = path.to_string_lossy().to_string();
+            match GeoFile::open(&filepath, TILE_CACHE_SIZE) {
+                Ok(geo_file) => tiles.push(TileInfo {
+                    name: file_name(&filepath),
+                    filepath,
+                    bounds: geo_file.bounds(),
+                    resolution: geo_file.resolution(),
+                }),
+                Err(e) => error!("Skipping DEM tile '{filepath}': {e}"),
+            }
+        }
+
+        tiles.sort_by(|a, b| a.resolution.total_cmp(&b.resolution));
+
+        tiles
+    }
+
+    /// Keeps at most MAX_OPEN_TILES tiles open.
+    fn open_tile(&mut self, i: usize) -> bool {
+        if self.tiles[i].geo_file.is_some() {
+            return true;
+        }
+        if self.tiles[i].broken {
+            return false;
+        }
+
+        let num_open = self.tiles.iter().filter(|t| t.geo_file.is_some()).count();
+        if num_open >= MAX_OPEN_TILES {
+            if let Some(lru) = self.tiles.iter_mut().filter(|t| t.geo_file.is_some()).min_by_key(|t| t.last_used) {
+                lru.geo_file = None;
+            }
+        }
+
+        let (info, tile) = (&self.index[i], &mut self.tiles[i]);
+        match GeoFile::open(&info.filepath, TILE_CACHE_SIZE) {
+            Ok(geo_file) => {
+                tile.geo_file = Some(geo_file);
+                true
+            },
+            Err(e) => {
+                error!("Could not open DEM tile '{}': {e}", info.filepath);
+                tile.broken = true;
+                false
+            }
+        }
+    }
+
+    /// @return ([m] terrain elevation, name of the source which answered)
+    pub fn get_elevation(&mut self, lat: f64, lon: f64) -> Option<(f64, &str)> {
+        self.tick += 1;
+
+        for i in 0..self.tiles.len() {
+            if !self.index[i].contains(lat, lon) || !self.open_tile(i) {
+                continue;
+            }
+
+            let tile = &mut self.tiles[i];
+            tile.last_used = self.tick;
+            if let Some(elevation) = tile.geo_file.as_mut().and_then(|gf| gf.get_elevation(lat, lon)) {
+                return Some((elevation, &self.index[i].name));
+            }
+        }
+
+        let elevation = self.fallback.as_mut()?.get_elevation(lat, lon)?;
+        Some((elevation, &self.fallback_name))
+    }
+
+    /// @return [m] terrain elevation rounded to whole meters
+    pub fn get_value(&mut self, lat: f64, lon: f64) -> Option<i64> {
+        self.get_elevation(lat, lon).map(|(e, _)| e.round() as i64)
+    }
+}
+
+/// Terrain of the cron jobs; the tiles directory is scanned once, not on every run.
+pub fn shared() -> MutexGuard<'static, Terrain> {
+    SHARED_TERRAIN.lock().unwrap()
+}
+
+fn file_name(filepath: &str) -> String {
+    Path::new(filepath).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(filepath.into())
+}